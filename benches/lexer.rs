@@ -0,0 +1,116 @@
+//! Compares the allocations made by lexing a large generated script into owned tokens,
+//! as `tokenize` used to, against streaming borrowed tokens from `Lexer`, and measures
+//! parsing it, which lexes as it goes and keeps owned tokens in the tree.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use monkey::lexer::Lexer;
+use monkey::parser::Parser;
+use monkey::token::Token;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const SNIPPET: &str = r#"
+let fibonacci_比較 = fn(n) {
+  if (n <= 1) { return n; } else { fibonacci_比較(n - 1) + fibonacci_比較(n - 2) }
+};
+let total = 0;
+for (x in [1, 0x10, 0b1010, 1_000_000]) {
+  total += x * 2 % 7; // accumulate
+}
+while (total > 0 && total != 3) { total = total >> 1; }
+let greeting = "hello, \"monkey\"";
+"#;
+const REPETITIONS: usize = 20_000;
+const RUNS: usize = 5;
+
+struct Measurement {
+    // Tokens lexed, or statements parsed.
+    items: usize,
+    allocations: usize,
+    bytes: usize,
+    best: Duration,
+}
+
+fn measure(source: &str, run: impl Fn(&str) -> usize) -> Measurement {
+    let mut measurement = Measurement {
+        items: 0,
+        allocations: 0,
+        bytes: 0,
+        best: Duration::MAX,
+    };
+    for _ in 0..RUNS {
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+        let started = Instant::now();
+        measurement.items = black_box(run(black_box(source)));
+        measurement.best = measurement.best.min(started.elapsed());
+        measurement.allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        measurement.bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+    }
+    measurement
+}
+
+fn report(name: &str, m: &Measurement) {
+    println!(
+        "{:<10} {:>10} {:>12} {:>14} {:>12.2?}",
+        name, m.items, m.allocations, m.bytes, m.best
+    );
+}
+
+fn main() {
+    let source = SNIPPET.repeat(REPETITIONS);
+
+    let eager = measure(&source, |source| {
+        let tokens: Vec<Token<'static>> = Lexer::new(source).map(Token::into_owned).collect();
+        tokens.len()
+    });
+    let streaming = measure(&source, |source| Lexer::new(source).count());
+    let parsing = measure(&source, |source| {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse();
+        assert!(parser.errors.is_empty());
+        program.statements.len()
+    });
+
+    println!(
+        "lexing and parsing {} bytes of source, best of {} runs",
+        source.len(),
+        RUNS
+    );
+    println!(
+        "{:<10} {:>10} {:>12} {:>14} {:>12}",
+        "", "items", "allocations", "bytes", "time"
+    );
+    report("eager", &eager);
+    report("streaming", &streaming);
+    report("parsing", &parsing);
+}
@@ -1,47 +1,343 @@
-use crate::token::Token;
+use crate::symbol::Symbol;
+use crate::token::{Position, Span, Token};
+use num_bigint::BigInt;
 use std::fmt::Debug;
+use std::ops::Index;
+use std::rc::Rc;
 
 pub trait Node {
     fn token_literal(&self) -> String;
-    fn string(&self) -> String;
+    /// Children are stored in the arena, so printing a node needs the `Ast` it belongs to.
+    fn string(&self, ast: &Ast) -> String;
 }
 
 pub trait Statement: Node + Debug {
+    #[allow(dead_code)]
     fn statement_node(&self);
 }
 
 pub trait Expression: Node + Debug {
+    #[allow(dead_code)]
     fn expression_node(&self);
 }
 
-#[derive(Debug)]
-pub struct Program {
-    pub statements: Vec<Box<dyn Statement>>,
-}
+/// Index of an expression node in an `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExpressionId(u32);
+
+/// Index of a statement node in an `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StatementId(u32);
+
+/// Index of a block in an `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(u32);
+
+// The arena keeps nodes by value in one vector per kind; these enums list the node types
+// each vector can hold. Passes match on them, so adding a node type makes every pass that
+// doesn't handle it fail to compile, and indexing hands them out as trait objects.
+macro_rules! node_storage {
+    ($storage:ident: $node_trait:ident { $($node:ident),* $(,)? }) => {
+        // Variants are named after the node types they hold.
+        #[allow(clippy::enum_variant_names)]
+        #[derive(Debug, Clone)]
+        pub enum $storage {
+            $($node($node),)*
+        }
 
-impl Node for Program {
-    fn token_literal(&self) -> String {
-        if self.statements.len() > 0 {
-            self.statements[0].token_literal()
-        } else {
-            "".to_string()
+        impl $storage {
+            fn as_node(&self) -> &(dyn $node_trait + 'static) {
+                match self {
+                    $($storage::$node(node) => node,)*
+                }
+            }
+
+            fn token(&self) -> &Token<'static> {
+                match self {
+                    $($storage::$node(node) => &node.token,)*
+                }
+            }
         }
+
+        $(
+            impl From<$node> for $storage {
+                fn from(node: $node) -> Self {
+                    $storage::$node(node)
+                }
+            }
+        )*
+    };
+}
+
+node_storage!(ExpressionNode: Expression {
+    Identifier,
+    IntegerLiteral,
+    StringLiteral,
+    Boolean,
+    NullLiteral,
+    FunctionLiteral,
+    PrefixExpression,
+    InfixExpression,
+    IfExpression,
+    CallExpression,
+    ArrayLiteral,
+    IndexExpression,
+    AssignExpression,
+});
+
+node_storage!(StatementNode: Statement {
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    WhileStatement,
+    ForStatement,
+    BreakStatement,
+    ContinueStatement,
+});
+
+/// Owns every node of a parsed program. Nodes refer to their children by id, so the whole
+/// tree is freed at once and other passes can key side tables by node id.
+#[derive(Debug, Clone, Default)]
+pub struct Ast {
+    expressions: Vec<ExpressionNode>,
+    statements: Vec<StatementNode>,
+    blocks: Vec<BlockStatement>,
+}
+
+impl Ast {
+    pub fn add_expression(&mut self, node: impl Into<ExpressionNode>) -> ExpressionId {
+        self.expressions.push(node.into());
+        ExpressionId(next_id(&self.expressions))
+    }
+
+    pub fn add_statement(&mut self, node: impl Into<StatementNode>) -> StatementId {
+        self.statements.push(node.into());
+        StatementId(next_id(&self.statements))
+    }
+
+    pub fn add_block(&mut self, block: BlockStatement) -> BlockId {
+        self.blocks.push(block);
+        BlockId(next_id(&self.blocks))
+    }
+
+    /// Puts `node` in place of the expression at `id`, for passes that rewrite the tree.
+    pub fn replace_expression(&mut self, id: ExpressionId, node: impl Into<ExpressionNode>) {
+        self.expressions[id.0 as usize] = node.into();
+    }
+
+    pub fn replace_statement(&mut self, id: StatementId, node: impl Into<StatementNode>) {
+        self.statements[id.0 as usize] = node.into();
+    }
+
+    /// Makes the expression at `id` a copy of the one at `source`, sharing its children.
+    pub fn copy_expression(&mut self, id: ExpressionId, source: ExpressionId) {
+        self.expressions[id.0 as usize] = self.expressions[source.0 as usize].clone();
+    }
+
+    /// Every expression id, in the order the parser created the nodes.
+    #[allow(dead_code)]
+    pub fn expression_ids(&self) -> impl Iterator<Item = ExpressionId> {
+        (0..self.expressions.len() as u32).map(ExpressionId)
+    }
+
+    /// Every statement id, in the order the parser created the nodes.
+    pub fn statement_ids(&self) -> impl Iterator<Item = StatementId> {
+        (0..self.statements.len() as u32).map(StatementId)
+    }
+
+    /// The statement at `id`, for passes to match on its type.
+    pub fn statement(&self, id: StatementId) -> &StatementNode {
+        &self.statements[id.0 as usize]
+    }
+
+    /// The expression at `id`, for passes to match on its type.
+    pub fn expression(&self, id: ExpressionId) -> &ExpressionNode {
+        &self.expressions[id.0 as usize]
+    }
+
+    /// The token a statement was parsed from: its keyword, or the first token of an
+    /// expression statement.
+    pub fn statement_token(&self, id: StatementId) -> &Token<'static> {
+        self.statements[id.0 as usize].token()
+    }
+
+    /// The token an expression was parsed from, such as its operator or its `(`.
+    pub fn expression_token(&self, id: ExpressionId) -> &Token<'static> {
+        self.expressions[id.0 as usize].token()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.expressions.len() + self.statements.len() + self.blocks.len()
+    }
+
+    /// Passes the position of every token under `statement` through `f`, so a statement can
+    /// be reused after an edit moves the text it was parsed from. Each node is visited once
+    /// for every parent it has, so this is meant for trees as the parser builds them.
+    pub fn map_positions(
+        &mut self,
+        statement: StatementId,
+        f: &mut impl FnMut(Position) -> Position,
+    ) {
+        let mut statements = vec![statement];
+        let mut expressions = Vec::new();
+        let mut blocks = Vec::new();
+
+        loop {
+            if let Some(id) = statements.pop() {
+                match &mut self.statements[id.0 as usize] {
+                    StatementNode::LetStatement(node) => {
+                        map_token(&mut node.token, f);
+                        map_token(&mut node.name.token, f);
+                        expressions.push(node.value);
+                    }
+                    StatementNode::ReturnStatement(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.push(node.return_value);
+                    }
+                    StatementNode::ExpressionStatement(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.push(node.expression);
+                    }
+                    StatementNode::WhileStatement(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.push(node.condition);
+                        blocks.push(node.body);
+                    }
+                    StatementNode::ForStatement(node) => {
+                        map_token(&mut node.token, f);
+                        map_token(&mut node.variable.token, f);
+                        expressions.push(node.iterable);
+                        blocks.push(node.body);
+                    }
+                    StatementNode::BreakStatement(node) => map_token(&mut node.token, f),
+                    StatementNode::ContinueStatement(node) => map_token(&mut node.token, f),
+                }
+            } else if let Some(id) = blocks.pop() {
+                let block = &mut self.blocks[id.0 as usize];
+                map_token(&mut block.token, f);
+                block.end = map_span(block.end, f);
+                statements.extend(&block.statements);
+            } else if let Some(id) = expressions.pop() {
+                match &mut self.expressions[id.0 as usize] {
+                    ExpressionNode::Identifier(node) => map_token(&mut node.token, f),
+                    ExpressionNode::IntegerLiteral(node) => map_token(&mut node.token, f),
+                    ExpressionNode::StringLiteral(node) => map_token(&mut node.token, f),
+                    ExpressionNode::Boolean(node) => map_token(&mut node.token, f),
+                    ExpressionNode::NullLiteral(node) => map_token(&mut node.token, f),
+                    ExpressionNode::FunctionLiteral(node) => {
+                        map_token(&mut node.token, f);
+                        for parameter in &mut node.parameters {
+                            map_token(&mut parameter.token, f);
+                        }
+                        blocks.push(node.body);
+                    }
+                    ExpressionNode::PrefixExpression(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.push(node.right);
+                    }
+                    ExpressionNode::InfixExpression(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.extend([node.left, node.right]);
+                    }
+                    ExpressionNode::IfExpression(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.push(node.condition);
+                        blocks.push(node.consequence);
+                        match node.alternative {
+                            Some(ElseBranch::Block(block)) => blocks.push(block),
+                            Some(ElseBranch::If(expression)) => expressions.push(expression),
+                            None => {}
+                        }
+                    }
+                    ExpressionNode::CallExpression(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.push(node.function);
+                        expressions.extend(&node.arguments);
+                    }
+                    ExpressionNode::ArrayLiteral(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.extend(&node.elements);
+                    }
+                    ExpressionNode::IndexExpression(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.extend([node.left, node.index]);
+                    }
+                    ExpressionNode::AssignExpression(node) => {
+                        map_token(&mut node.token, f);
+                        expressions.extend([node.target, node.value]);
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+fn map_token(token: &mut Token, f: &mut impl FnMut(Position) -> Position) {
+    token.span = map_span(token.span, f);
+}
+
+fn map_span(span: Span, f: &mut impl FnMut(Position) -> Position) -> Span {
+    Span {
+        start: f(span.start),
+        end: f(span.end),
+    }
+}
+
+// The id of the node just pushed onto `nodes`.
+fn next_id<T>(nodes: &[T]) -> u32 {
+    u32::try_from(nodes.len() - 1).expect("too many nodes for one program")
+}
+
+impl Index<ExpressionId> for Ast {
+    type Output = dyn Expression;
+
+    fn index(&self, id: ExpressionId) -> &Self::Output {
+        self.expressions[id.0 as usize].as_node()
+    }
+}
+
+impl Index<StatementId> for Ast {
+    type Output = dyn Statement;
+
+    fn index(&self, id: StatementId) -> &Self::Output {
+        self.statements[id.0 as usize].as_node()
     }
+}
+
+impl Index<BlockId> for Ast {
+    type Output = BlockStatement;
+
+    fn index(&self, id: BlockId) -> &Self::Output {
+        &self.blocks[id.0 as usize]
+    }
+}
+
+/// A parsed program: the top-level statements and the arena holding them.
+/// Function values keep the arena alive after the program itself is dropped.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub ast: Rc<Ast>,
+    pub statements: Vec<StatementId>,
+}
 
-    fn string(&self) -> String {
+impl Program {
+    #[allow(dead_code)]
+    pub fn string(&self) -> String {
         let mut out = String::new();
         for statement in &self.statements {
-            out += &statement.string();
+            out += &self.ast[*statement].string(&self.ast);
         }
         out
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LetStatement {
-    pub token: Token,
+    pub token: Token<'static>,
     pub name: Identifier,
-    pub value: Box<dyn Expression>,
+    pub value: ExpressionId,
 }
 
 impl Statement for LetStatement {
@@ -50,17 +346,22 @@ impl Statement for LetStatement {
 
 impl Node for LetStatement {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
-    fn string(&self) -> String {
-        self.token_literal() + " " + &self.name.string() + " = " + &self.value.string() + ";"
+    fn string(&self, ast: &Ast) -> String {
+        self.token_literal()
+            + " "
+            + &self.name.string(ast)
+            + " = "
+            + &ast[self.value].string(ast)
+            + ";"
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ReturnStatement {
-    pub token: Token,
-    pub return_value: Box<dyn Expression>,
+    pub token: Token<'static>,
+    pub return_value: ExpressionId,
 }
 
 impl Statement for ReturnStatement {
@@ -69,17 +370,17 @@ impl Statement for ReturnStatement {
 
 impl Node for ReturnStatement {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
-    fn string(&self) -> String {
-        self.token_literal() + " " + &self.return_value.string() + ";"
+    fn string(&self, ast: &Ast) -> String {
+        self.token_literal() + " " + &ast[self.return_value].string(ast) + ";"
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ExpressionStatement {
-    pub token: Token,
-    pub expression: Box<dyn Expression>,
+    pub token: Token<'static>,
+    pub expression: ExpressionId,
 }
 
 impl Statement for ExpressionStatement {
@@ -88,18 +389,20 @@ impl Statement for ExpressionStatement {
 
 impl Node for ExpressionStatement {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
-        self.expression.string()
+    fn string(&self, ast: &Ast) -> String {
+        ast[self.expression].string(ast)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BlockStatement {
-    pub token: Token,
-    pub statements: Vec<Box<dyn Statement>>,
+    pub token: Token<'static>,
+    pub statements: Vec<StatementId>,
+    /// The closing brace, so passes can tell which positions the block covers.
+    pub end: Span,
 }
 
 impl Statement for BlockStatement {
@@ -108,22 +411,33 @@ impl Statement for BlockStatement {
 
 impl Node for BlockStatement {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
+    fn string(&self, ast: &Ast) -> String {
         let mut out = String::new();
         for statement in &self.statements {
-            out += &statement.string();
+            out += &ast[*statement].string(ast);
         }
         out
     }
 }
 
-#[derive(Debug)]
+/// Where the resolver found the declaration an identifier refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// Slot `slot` of the environment `depth` scopes out from the one the identifier is in.
+    Local { depth: usize, slot: usize },
+    /// A top-level binding, looked up by name in the outermost environment.
+    Global,
+}
+
+#[derive(Debug, Clone)]
 pub struct Identifier {
-    pub token: Token,
-    pub value: String,
+    pub token: Token<'static>,
+    pub value: Symbol,
+    /// `None` until the program has been resolved; unresolved names are looked up by name.
+    pub binding: Option<Binding>,
 }
 
 impl Expression for Identifier {
@@ -132,18 +446,18 @@ impl Expression for Identifier {
 
 impl Node for Identifier {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
-        self.value.clone()
+    fn string(&self, _ast: &Ast) -> String {
+        self.value.to_string()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IntegerLiteral {
-    pub token: Token,
-    pub value: i64,
+    pub token: Token<'static>,
+    pub value: BigInt,
 }
 
 impl Expression for IntegerLiteral {
@@ -152,19 +466,39 @@ impl Expression for IntegerLiteral {
 
 impl Node for IntegerLiteral {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, _ast: &Ast) -> String {
+        self.token.literal.to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StringLiteral {
+    pub token: Token<'static>,
+    pub value: String,
+}
+
+impl Expression for StringLiteral {
+    fn expression_node(&self) {}
+}
+
+impl Node for StringLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
-        self.token.literal.clone()
+    fn string(&self, _ast: &Ast) -> String {
+        format!("\"{}\"", self.token.literal)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FunctionLiteral {
-    pub token: Token,
+    pub token: Token<'static>,
     pub parameters: Vec<Identifier>,
-    pub body: BlockStatement,
+    pub body: BlockId,
 }
 
 impl Expression for FunctionLiteral {
@@ -173,24 +507,24 @@ impl Expression for FunctionLiteral {
 
 impl Node for FunctionLiteral {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
+    fn string(&self, ast: &Ast) -> String {
         let mut out = String::new();
-        let params: Vec<String> = self.parameters.iter().map(|p| p.string()).collect();
+        let params: Vec<String> = self.parameters.iter().map(|p| p.string(ast)).collect();
         out += &self.token_literal();
         out += "(";
         out += &params.join(", ");
         out += ")";
-        out += &self.body.string();
+        out += &ast[self.body].string(ast);
         out
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Boolean {
-    pub token: Token,
+    pub token: Token<'static>,
     pub value: bool,
 }
 
@@ -200,19 +534,38 @@ impl Expression for Boolean {
 
 impl Node for Boolean {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
-        self.token.literal.clone()
+    fn string(&self, _ast: &Ast) -> String {
+        self.token.literal.to_string()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+pub struct NullLiteral {
+    pub token: Token<'static>,
+}
+
+impl Expression for NullLiteral {
+    fn expression_node(&self) {}
+}
+
+impl Node for NullLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, _ast: &Ast) -> String {
+        self.token.literal.to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PrefixExpression {
-    pub token: Token,
+    pub token: Token<'static>,
     pub operator: String,
-    pub right: Box<dyn Expression>,
+    pub right: ExpressionId,
 }
 
 impl Expression for PrefixExpression {
@@ -221,25 +574,25 @@ impl Expression for PrefixExpression {
 
 impl Node for PrefixExpression {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
+    fn string(&self, ast: &Ast) -> String {
         let mut out = String::new();
         out += "(";
         out += &self.operator;
-        out += &self.right.string();
+        out += &ast[self.right].string(ast);
         out += ")";
         out
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InfixExpression {
-    pub token: Token,
-    pub left: Box<dyn Expression>,
+    pub token: Token<'static>,
+    pub left: ExpressionId,
     pub operator: String,
-    pub right: Box<dyn Expression>,
+    pub right: ExpressionId,
 }
 
 impl Expression for InfixExpression {
@@ -248,28 +601,36 @@ impl Expression for InfixExpression {
 
 impl Node for InfixExpression {
     fn token_literal(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
+    fn string(&self, ast: &Ast) -> String {
         let mut out = String::new();
         out += "(";
-        out += &self.left.string();
+        out += &ast[self.left].string(ast);
         out += " ";
         out += &self.operator;
         out += " ";
-        out += &self.right.string();
+        out += &ast[self.right].string(ast);
         out += ")";
         out
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IfExpression {
-    pub token: Token,
-    pub condition: Box<dyn Expression>,
-    pub consequence: BlockStatement,
-    pub alternative: Option<BlockStatement>,
+    pub token: Token<'static>,
+    pub condition: ExpressionId,
+    pub consequence: BlockId,
+    pub alternative: Option<ElseBranch>,
+}
+
+/// What follows `else`: either a plain block or the next `if` of an `else if` chain.
+#[derive(Debug, Clone)]
+pub enum ElseBranch {
+    Block(BlockId),
+    /// Always an `IfExpression`.
+    If(ExpressionId),
 }
 
 impl Expression for IfExpression {
@@ -278,22 +639,248 @@ impl Expression for IfExpression {
 
 impl Node for IfExpression {
     fn token_literal(&self) -> String {
-        self.token_literal().clone()
+        self.token.literal.to_string()
     }
 
-    fn string(&self) -> String {
+    fn string(&self, ast: &Ast) -> String {
         let mut out = String::new();
         out += "if ";
-        out += &self.condition.string();
+        out += &condition_string(ast, self.condition);
         out += " { ";
-        out += &self.consequence.string();
+        out += &ast[self.consequence].string(ast);
         out += " }";
-        if let Some(alternative) = &self.alternative {
-            out += " else";
-            out += " { ";
-            out += &alternative.string();
-            out += " }";
+        match &self.alternative {
+            Some(ElseBranch::Block(alternative)) => {
+                out += " else";
+                out += " { ";
+                out += &ast[*alternative].string(ast);
+                out += " }";
+            }
+            Some(ElseBranch::If(alternative)) => {
+                out += " else ";
+                out += &ast[*alternative].string(ast);
+            }
+            None => {}
         }
         out
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct CallExpression {
+    pub token: Token<'static>,
+    pub function: ExpressionId,
+    pub arguments: Vec<ExpressionId>,
+    /// `f?.(x)`: evaluates to null instead of calling when `function` is null.
+    pub optional: bool,
+}
+
+impl Expression for CallExpression {
+    fn expression_node(&self) {}
+}
+
+impl Node for CallExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, ast: &Ast) -> String {
+        let mut out = String::new();
+        let args: Vec<String> = self.arguments.iter().map(|a| ast[*a].string(ast)).collect();
+        out += &ast[self.function].string(ast);
+        if self.optional {
+            out += "?.";
+        }
+        out += "(";
+        out += &args.join(", ");
+        out += ")";
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArrayLiteral {
+    pub token: Token<'static>,
+    pub elements: Vec<ExpressionId>,
+}
+
+impl Expression for ArrayLiteral {
+    fn expression_node(&self) {}
+}
+
+impl Node for ArrayLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, ast: &Ast) -> String {
+        let elements: Vec<String> = self.elements.iter().map(|e| ast[*e].string(ast)).collect();
+        format!("[{}]", elements.join(", "))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexExpression {
+    pub token: Token<'static>,
+    pub left: ExpressionId,
+    pub index: ExpressionId,
+    /// `xs?.[i]`: evaluates to null instead of indexing when `left` is null.
+    pub optional: bool,
+}
+
+impl Expression for IndexExpression {
+    fn expression_node(&self) {}
+}
+
+impl Node for IndexExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, ast: &Ast) -> String {
+        let chain = if self.optional { "?." } else { "" };
+        format!(
+            "({}{}[{}])",
+            ast[self.left].string(ast),
+            chain,
+            ast[self.index].string(ast)
+        )
+    }
+}
+
+/// `target = value` or a compound form such as `target += value`.
+/// The target is always an `Identifier` or an `IndexExpression`.
+#[derive(Debug, Clone)]
+pub struct AssignExpression {
+    pub token: Token<'static>,
+    pub target: ExpressionId,
+    pub operator: String,
+    pub value: ExpressionId,
+}
+
+impl Expression for AssignExpression {
+    fn expression_node(&self) {}
+}
+
+impl Node for AssignExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, ast: &Ast) -> String {
+        format!(
+            "({} {} {})",
+            ast[self.target].string(ast),
+            self.operator,
+            ast[self.value].string(ast)
+        )
+    }
+}
+
+// A condition in the parentheses `if` and `while` need around it. Operators print inside
+// parentheses of their own already, which serve.
+fn condition_string(ast: &Ast, condition: ExpressionId) -> String {
+    let string = ast[condition].string(ast);
+    match ast.expressions[condition.0 as usize] {
+        ExpressionNode::PrefixExpression(_)
+        | ExpressionNode::InfixExpression(_)
+        | ExpressionNode::IndexExpression(_)
+        | ExpressionNode::AssignExpression(_) => string,
+        _ => format!("({})", string),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WhileStatement {
+    pub token: Token<'static>,
+    pub condition: ExpressionId,
+    pub body: BlockId,
+}
+
+impl Statement for WhileStatement {
+    fn statement_node(&self) {}
+}
+
+impl Node for WhileStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, ast: &Ast) -> String {
+        let mut out = String::new();
+        out += "while ";
+        out += &condition_string(ast, self.condition);
+        out += " { ";
+        out += &ast[self.body].string(ast);
+        out += " }";
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ForStatement {
+    pub token: Token<'static>,
+    pub variable: Identifier,
+    pub iterable: ExpressionId,
+    pub body: BlockId,
+}
+
+impl Statement for ForStatement {
+    fn statement_node(&self) {}
+}
+
+impl Node for ForStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, ast: &Ast) -> String {
+        let mut out = String::new();
+        out += "for (";
+        out += &self.variable.string(ast);
+        out += " in ";
+        out += &ast[self.iterable].string(ast);
+        out += ") { ";
+        out += &ast[self.body].string(ast);
+        out += " }";
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakStatement {
+    pub token: Token<'static>,
+}
+
+impl Statement for BreakStatement {
+    fn statement_node(&self) {}
+}
+
+impl Node for BreakStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, _ast: &Ast) -> String {
+        self.token_literal() + ";"
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContinueStatement {
+    pub token: Token<'static>,
+}
+
+impl Statement for ContinueStatement {
+    fn statement_node(&self) {}
+}
+
+impl Node for ContinueStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn string(&self, _ast: &Ast) -> String {
+        self.token_literal() + ";"
+    }
+}
@@ -0,0 +1,713 @@
+use crate::ast::Program;
+use crate::debugger::{Debugger, Frontend, Reason, Resume, Session};
+use crate::environment::Environment;
+use crate::evaluator::IntegerMode;
+use crate::interpreter::Interpreter;
+use crate::limits::Limits;
+use crate::lsp::{read_message, write_message};
+use crate::object::{Array, Object};
+use crate::symbol::Symbol;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::{fs, mem};
+
+// Scripts are single-threaded, so every request about a thread is about this one.
+const THREAD_ID: u64 = 1;
+
+/// Serves the Debug Adapter Protocol over `input` and `output`: launches the script the
+/// client names and debugs it until the client disconnects or closes the stream.
+///
+/// Lines and columns are counted from 1, in characters, as the lexer counts them. Requests
+/// are only read while the script is paused, so a running script can't be interrupted.
+pub fn run(
+    integer_mode: IntegerMode,
+    limits: Limits,
+    input: impl BufRead + 'static,
+    output: impl Write + 'static,
+) -> io::Result<()> {
+    let mut adapter = Adapter {
+        input,
+        output,
+        integer_mode,
+        limits,
+        seq: 0,
+        launched: None,
+        program: None,
+        breakpoints: BTreeSet::new(),
+        stop_on_entry: false,
+        handles: Vec::new(),
+        disconnected: false,
+        error: None,
+    };
+    let Some((mut interpreter, program)) = adapter.configure()? else {
+        return Ok(());
+    };
+
+    let debugger = Debugger::new(adapter, integer_mode, limits);
+    let (result, debugger) = interpreter.run_hooked(&program, debugger);
+    let mut adapter = debugger.into_frontend();
+    if let Some(error) = adapter.error.take() {
+        return Err(error);
+    }
+    if adapter.disconnected {
+        return Ok(());
+    }
+    adapter.finish(&result)?;
+    while !adapter.disconnected {
+        let Some(message) = read_message(&mut adapter.input)? else {
+            break;
+        };
+        adapter.handle(&message, None)?;
+    }
+    Ok(())
+}
+
+struct Adapter<R, W> {
+    input: R,
+    output: W,
+    // What the script runs in, and within, once it's launched.
+    integer_mode: IntegerMode,
+    limits: Limits,
+    // The sequence number of the last message we sent.
+    seq: u64,
+    launched: Option<Launched>,
+    // The script, compiled by the interpreter it will run in. Taken when the script starts.
+    program: Option<(Interpreter, Program)>,
+    // Breakpoints set before the script starts, which move into the session once it does.
+    breakpoints: BTreeSet<usize>,
+    stop_on_entry: bool,
+    // What each `variablesReference` handed out since the last pause refers to, counting
+    // from 1.
+    handles: Vec<Handle>,
+    disconnected: bool,
+    error: Option<io::Error>,
+}
+
+struct Launched {
+    path: String,
+    // Lines a statement starts on, where a breakpoint can pause.
+    statement_lines: BTreeSet<usize>,
+}
+
+enum Handle {
+    /// The bindings from a scope out to, but not including, the global one.
+    Locals(Rc<RefCell<Environment>>),
+    Globals(Rc<RefCell<Environment>>),
+    Array(Rc<RefCell<Array>>),
+}
+
+impl<R: BufRead, W: Write> Frontend for Adapter<R, W> {
+    fn paused(&mut self, session: &mut Session, reason: Reason) -> Resume {
+        match self.pause(session, reason) {
+            Ok(resume) => resume,
+            Err(error) => {
+                self.error = Some(error);
+                Resume::Quit
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Adapter<R, W> {
+    // Answers requests until the client is done configuring, and returns the script to run.
+    fn configure(&mut self) -> io::Result<Option<(Interpreter, Program)>> {
+        loop {
+            let Some(message) = read_message(&mut self.input)? else {
+                return Ok(None);
+            };
+            let started = self.handle(&message, None)?.is_some();
+            if self.disconnected {
+                return Ok(None);
+            }
+            if started {
+                return Ok(self.program.take());
+            }
+        }
+    }
+
+    fn pause(&mut self, session: &mut Session, mut reason: Reason) -> io::Result<Resume> {
+        if reason == Reason::Entry {
+            session.breakpoints = mem::take(&mut self.breakpoints);
+            let line = session
+                .frames()
+                .next()
+                .map_or(0, |frame| frame.position.line);
+            if session.breakpoints.contains(&line) {
+                reason = Reason::Breakpoint;
+            } else if !self.stop_on_entry {
+                return Ok(Resume::Continue);
+            }
+        }
+
+        self.handles.clear();
+        let reason = match reason {
+            Reason::Entry => "entry",
+            Reason::Breakpoint => "breakpoint",
+            Reason::Step => "step",
+        };
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )?;
+
+        loop {
+            let Some(message) = read_message(&mut self.input)? else {
+                self.disconnected = true;
+                return Ok(Resume::Quit);
+            };
+            if let Some(resume) = self.handle(&message, Some(session))? {
+                return Ok(resume);
+            }
+        }
+    }
+
+    // Answers one request. Returns how to go on if the request starts or resumes the script.
+    fn handle(
+        &mut self,
+        request: &Value,
+        session: Option<&mut Session>,
+    ) -> io::Result<Option<Resume>> {
+        if request["type"] != "request" {
+            return Ok(None);
+        }
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+
+        let (result, resume) = match (command, session) {
+            ("initialize", _) => (
+                Ok(json!({ "supportsConfigurationDoneRequest": true })),
+                None,
+            ),
+            ("launch", _) => (self.launch(arguments), None),
+            ("setBreakpoints", session) => (Ok(self.set_breakpoints(arguments, session)), None),
+            ("configurationDone", _) if self.program.is_some() => {
+                (Ok(Value::Null), Some(Resume::Continue))
+            }
+            ("configurationDone", _) => (Err(String::from("no script has been launched")), None),
+            ("threads", _) => (
+                Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+                None,
+            ),
+            ("disconnect", _) => {
+                self.disconnected = true;
+                (Ok(Value::Null), Some(Resume::Quit))
+            }
+            ("stackTrace", Some(session)) => (Ok(self.stack_trace(session)), None),
+            ("scopes", Some(session)) => (self.scopes(session, arguments), None),
+            ("variables", Some(_)) => (self.variables(arguments), None),
+            ("evaluate", Some(session)) => (self.evaluate(session, arguments), None),
+            ("continue", Some(_)) => (
+                Ok(json!({ "allThreadsContinued": true })),
+                Some(Resume::Continue),
+            ),
+            ("next", Some(_)) => (Ok(Value::Null), Some(Resume::StepOver)),
+            ("stepIn", Some(_)) => (Ok(Value::Null), Some(Resume::StepIn)),
+            ("stepOut", Some(_)) => (Ok(Value::Null), Some(Resume::StepOut)),
+            (
+                "stackTrace" | "scopes" | "variables" | "evaluate" | "continue" | "next" | "stepIn"
+                | "stepOut",
+                None,
+            ) => (Err(String::from("the script isn't paused")), None),
+            _ => (Err(format!("unsupported request: {}", command)), None),
+        };
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)?;
+
+        // The client waits for this before it sends breakpoints.
+        if command == "launch" && self.program.is_some() {
+            self.event("initialized", Value::Null)?;
+        }
+        Ok(resume)
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or("no program to launch")?;
+        let source = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+
+        let mut interpreter = Interpreter::new(self.integer_mode);
+        interpreter.set_limits(self.limits);
+        let (program, _) = interpreter
+            .compile(&source)
+            .map_err(|error| error.to_string())?;
+
+        self.launched = Some(Launched {
+            path: path.to_string(),
+            statement_lines: program
+                .ast
+                .statement_ids()
+                .map(|id| program.ast.statement_token(id).span.start.line)
+                .collect(),
+        });
+        self.program = Some((interpreter, program));
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(Value::Null)
+    }
+
+    // There is only the one source, so every request replaces all the breakpoints.
+    fn set_breakpoints(&mut self, arguments: &Value, session: Option<&mut Session>) -> Value {
+        let requested: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+
+        let breakpoints = match session {
+            Some(session) => &mut session.breakpoints,
+            None => &mut self.breakpoints,
+        };
+        breakpoints.clear();
+        let mut verified = Vec::new();
+        for line in requested {
+            let valid = self
+                .launched
+                .as_ref()
+                .is_some_and(|launched| launched.statement_lines.contains(&line));
+            if valid {
+                breakpoints.insert(line);
+            }
+            verified.push(json!({ "verified": valid, "line": line }));
+        }
+        json!({ "breakpoints": verified })
+    }
+
+    fn stack_trace(&self, session: &Session) -> Value {
+        let frames: Vec<Value> = session
+            .frames()
+            .enumerate()
+            .map(|(id, frame)| {
+                json!({
+                    "id": id,
+                    "name": frame.name,
+                    "line": frame.position.line,
+                    "column": frame.position.column,
+                    "source": self.source(),
+                })
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn scopes(&mut self, session: &Session, arguments: &Value) -> Result<Value, String> {
+        let env = frame_env(session, &arguments["frameId"])?;
+        let mut globals = Rc::clone(&env);
+        loop {
+            let Some(outer) = globals.borrow().outer().cloned() else {
+                break;
+            };
+            globals = outer;
+        }
+
+        let locals = self.handle_for(Handle::Locals(env));
+        let globals = self.handle_for(Handle::Globals(globals));
+        Ok(json!({
+            "scopes": [
+                { "name": "Locals", "variablesReference": locals, "expensive": false },
+                { "name": "Globals", "variablesReference": globals, "expensive": false },
+            ],
+        }))
+    }
+
+    fn variables(&mut self, arguments: &Value) -> Result<Value, String> {
+        let reference = arguments["variablesReference"].as_u64().unwrap_or_default() as usize;
+        let bindings: Vec<(String, Object)> = match reference
+            .checked_sub(1)
+            .and_then(|index| self.handles.get(index))
+        {
+            Some(Handle::Locals(env)) => {
+                let mut seen: HashSet<Symbol> = HashSet::new();
+                let mut bindings = Vec::new();
+                let mut scope = Rc::clone(env);
+                loop {
+                    let Some(outer) = scope.borrow().outer().cloned() else {
+                        break;
+                    };
+                    for (name, value) in scope.borrow().bindings() {
+                        if seen.insert(name) {
+                            bindings.push((name.to_string(), value));
+                        }
+                    }
+                    scope = outer;
+                }
+                bindings
+            }
+            Some(Handle::Globals(env)) => env
+                .borrow()
+                .bindings()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            Some(Handle::Array(elements)) => elements
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (index.to_string(), value.clone()))
+                .collect(),
+            None => return Err(format!("unknown variablesReference: {}", reference)),
+        };
+
+        let variables: Vec<Value> = bindings
+            .into_iter()
+            .map(|(name, value)| {
+                let mut variable = self.value(&value);
+                variable["name"] = json!(name);
+                variable
+            })
+            .collect();
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn evaluate(&mut self, session: &Session, arguments: &Value) -> Result<Value, String> {
+        let expression = arguments["expression"].as_str().unwrap_or_default();
+        let env = frame_env(session, &arguments["frameId"])?;
+        match session.evaluate(expression, &env) {
+            Ok(Object::Error(message)) => Err(message),
+            Ok(value) => {
+                let mut result = self.value(&value);
+                result["result"] = result["value"].take();
+                Ok(result)
+            }
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    // Describes a value for the client. Arrays get a reference so their elements can be
+    // expanded.
+    fn value(&mut self, value: &Object) -> Value {
+        let reference = match value {
+            Object::Array(elements) => self.handle_for(Handle::Array(Rc::clone(elements))),
+            _ => 0,
+        };
+        let text = match value {
+            Object::String(text) => format!("{:?}", text),
+            value => value.to_string(),
+        };
+        json!({ "value": text, "type": value.type_name(), "variablesReference": reference })
+    }
+
+    fn handle_for(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+
+    fn source(&self) -> Value {
+        let Some(launched) = &self.launched else {
+            return Value::Null;
+        };
+        let name = Path::new(&launched.path)
+            .file_name()
+            .map(|name| name.to_string_lossy());
+        json!({ "name": name, "path": launched.path })
+    }
+
+    fn finish(&mut self, result: &Object) -> io::Result<()> {
+        self.event(
+            "output",
+            json!({ "category": "console", "output": format!("{}\n", result) }),
+        )?;
+        let exit_code = if result.is_error() { 1 } else { 0 };
+        self.event("exited", json!({ "exitCode": exit_code }))?;
+        self.event("terminated", Value::Null)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+}
+
+// The scope a frame is paused in. Without a frame id, the innermost frame's.
+fn frame_env(session: &Session, frame_id: &Value) -> Result<Rc<RefCell<Environment>>, String> {
+    let index = frame_id.as_u64().unwrap_or_default() as usize;
+    let frame = session
+        .frames()
+        .nth(index)
+        .ok_or_else(|| format!("unknown frameId: {}", frame_id))?;
+    frame
+        .env
+        .clone()
+        .ok_or_else(|| format!("{} hasn't started running", frame.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Script(std::path::PathBuf);
+
+    impl Script {
+        fn new(name: &str, source: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("monkey-dap-{}-{}.mk", std::process::id(), name));
+            fs::write(&path, source).unwrap();
+            Script(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for Script {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn request(seq: u64, command: &str, arguments: Value) -> Value {
+        json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    // Runs the adapter over `requests` and returns everything it sent back.
+    fn exchange(requests: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for request in requests {
+            write_message(&mut input, request).unwrap();
+        }
+        let output = Rc::new(RefCell::new(Vec::new()));
+        run(
+            IntegerMode::default(),
+            Limits::default(),
+            io::Cursor::new(input),
+            Output(output.clone()),
+        )
+        .unwrap();
+
+        let output = output.borrow();
+        let mut output = &output[..];
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Events are named by `event`, responses by the command they answer.
+    fn summary(replies: &[Value]) -> Vec<String> {
+        replies
+            .iter()
+            .map(|reply| match reply["type"].as_str() {
+                Some("event") => format!("event {}", reply["event"].as_str().unwrap()),
+                _ => format!(
+                    "{} {}",
+                    reply["command"].as_str().unwrap(),
+                    reply["success"]
+                ),
+            })
+            .collect()
+    }
+
+    fn find<'a>(replies: &'a [Value], command: &str) -> Vec<&'a Value> {
+        replies
+            .iter()
+            .filter(|reply| reply["command"] == command || reply["event"] == command)
+            .collect()
+    }
+
+    const PROGRAM: &str = "let add = fn(a, b) {
+  let sum = a + b;
+  sum
+};
+let xs = [1, \"two\"];
+let total = add(1, 2);
+total";
+
+    #[test]
+    fn test_session() {
+        let script = Script::new("session", PROGRAM);
+        let replies = exchange(&[
+            request(1, "initialize", json!({ "adapterID": "monkey" })),
+            request(2, "launch", json!({ "program": script.path() })),
+            request(
+                3,
+                "setBreakpoints",
+                json!({ "breakpoints": [{ "line": 2 }, { "line": 4 }] }),
+            ),
+            request(4, "configurationDone", json!({})),
+            request(5, "stackTrace", json!({ "threadId": 1 })),
+            request(6, "scopes", json!({ "frameId": 0 })),
+            request(7, "variables", json!({ "variablesReference": 1 })),
+            request(8, "variables", json!({ "variablesReference": 2 })),
+            request(9, "variables", json!({ "variablesReference": 3 })),
+            request(
+                10,
+                "evaluate",
+                json!({ "expression": "a + b * 10", "frameId": 0 }),
+            ),
+            request(11, "next", json!({ "threadId": 1 })),
+            request(12, "stepOut", json!({ "threadId": 1 })),
+            request(13, "continue", json!({ "threadId": 1 })),
+            request(14, "disconnect", json!({})),
+        ]);
+
+        assert_eq!(
+            summary(&replies),
+            [
+                "initialize true",
+                "launch true",
+                "event initialized",
+                "setBreakpoints true",
+                "configurationDone true",
+                "event stopped",
+                "stackTrace true",
+                "scopes true",
+                "variables true",
+                "variables true",
+                "variables true",
+                "evaluate true",
+                "next true",
+                "event stopped",
+                "stepOut true",
+                "event stopped",
+                "continue true",
+                "event output",
+                "event exited",
+                "event terminated",
+                "disconnect true",
+            ]
+        );
+        let seqs: Vec<u64> = replies
+            .iter()
+            .map(|reply| reply["seq"].as_u64().unwrap())
+            .collect();
+        assert_eq!(seqs, (1..=replies.len() as u64).collect::<Vec<_>>());
+
+        // Line 4 has no statement on it.
+        assert_eq!(
+            find(&replies, "setBreakpoints")[0]["body"]["breakpoints"],
+            json!([{ "verified": true, "line": 2 }, { "verified": false, "line": 4 }])
+        );
+
+        let stopped = find(&replies, "stopped");
+        assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+        assert_eq!(stopped[1]["body"]["reason"], "step");
+
+        let frames = &find(&replies, "stackTrace")[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "add");
+        assert_eq!(
+            (frames[0]["line"].clone(), frames[0]["column"].clone()),
+            (json!(2), json!(3))
+        );
+        assert_eq!(frames[1]["name"], "<main>");
+        assert_eq!(
+            (frames[1]["line"].clone(), frames[1]["column"].clone()),
+            (json!(6), json!(13))
+        );
+        assert_eq!(frames[0]["source"]["path"], script.path());
+
+        let variables = find(&replies, "variables");
+        assert_eq!(
+            variables[0]["body"]["variables"],
+            json!([
+                { "name": "a", "value": "1", "type": "INTEGER", "variablesReference": 0 },
+                { "name": "b", "value": "2", "type": "INTEGER", "variablesReference": 0 },
+            ])
+        );
+        let globals: Vec<&Value> = variables[1]["body"]["variables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variable| &variable["name"])
+            .collect();
+        assert_eq!(globals, ["add", "xs"]);
+        assert_eq!(
+            variables[2]["body"]["variables"][1],
+            json!({ "name": "1", "value": "\"two\"", "type": "STRING", "variablesReference": 0 })
+        );
+
+        assert_eq!(find(&replies, "evaluate")[0]["body"]["result"], "21");
+        assert_eq!(find(&replies, "output")[0]["body"]["output"], "3\n");
+        assert_eq!(find(&replies, "exited")[0]["body"]["exitCode"], 0);
+    }
+
+    #[test]
+    fn test_stop_on_entry_and_stepping_in() {
+        let script = Script::new("entry", PROGRAM);
+        let replies = exchange(&[
+            request(1, "initialize", json!({})),
+            request(
+                2,
+                "launch",
+                json!({ "program": script.path(), "stopOnEntry": true }),
+            ),
+            request(3, "configurationDone", json!({})),
+            request(4, "next", json!({})),
+            request(5, "next", json!({})),
+            request(6, "stepIn", json!({})),
+            request(7, "stackTrace", json!({})),
+            request(8, "disconnect", json!({})),
+        ]);
+
+        let stopped = find(&replies, "stopped");
+        assert_eq!(stopped[0]["body"]["reason"], "entry");
+        assert_eq!(stopped.len(), 4);
+        let frames = &find(&replies, "stackTrace")[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "add");
+        assert_eq!(frames[0]["line"], 2);
+        assert!(find(&replies, "terminated").is_empty());
+        assert_eq!(find(&replies, "disconnect")[0]["success"], true);
+    }
+
+    #[test]
+    fn test_errors() {
+        let script = Script::new("errors", "let x = y;");
+        let replies = exchange(&[
+            request(1, "configurationDone", json!({})),
+            request(2, "launch", json!({ "program": script.path() })),
+            request(3, "stackTrace", json!({})),
+            request(4, "pause", json!({})),
+        ]);
+
+        assert_eq!(
+            summary(&replies),
+            [
+                "configurationDone false",
+                "launch false",
+                "stackTrace false",
+                "pause false",
+            ]
+        );
+        assert_eq!(replies[1]["message"], "1:9: undefined identifier: y");
+        assert_eq!(replies[2]["message"], "the script isn't paused");
+        assert_eq!(replies[3]["message"], "unsupported request: pause");
+    }
+}
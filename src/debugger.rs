@@ -0,0 +1,518 @@
+use crate::ast::{Ast, CallExpression, StatementId};
+use crate::environment::Environment;
+use crate::evaluator::{Evaluator, Hook, IntegerMode};
+use crate::interpreter::{Error, Interpreter};
+use crate::lexer::Lexer;
+use crate::limits::Limits;
+use crate::object::Object;
+use crate::parser::Parser;
+use crate::resolver::{Diagnostic, Resolver};
+use crate::symbol::Symbol;
+use crate::token::Position;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::rc::Rc;
+
+// The error a program stops with when the debugger is told to quit.
+const STOPPED: &str = "stopped by the debugger";
+
+// The steps an expression evaluated at a pause may take when the session has no limit of its
+// own, so a mistake like `while (true) {}` can't hang it.
+const EVALUATE_STEPS: u64 = 1_000_000;
+
+/// How to go on from a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until a breakpoint.
+    Continue,
+    /// Pause at the very next statement, inside any function it calls.
+    StepIn,
+    /// Pause at the next statement of the current call or one of its callers.
+    StepOver,
+    /// Pause once the current call has returned.
+    StepOut,
+    /// Stop the program.
+    Quit,
+}
+
+/// Why the program paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Before the first statement.
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// A call in progress, or the program itself at the bottom of the stack.
+#[derive(Debug)]
+pub struct Frame {
+    /// The callee as written at the call site, such as `add` or `handlers[0]`.
+    pub name: String,
+    /// The statement running in this frame, or the call it is waiting on.
+    pub position: Position,
+    /// The innermost scope of that statement, once one has run.
+    pub env: Option<Rc<RefCell<Environment>>>,
+    // The line of the last statement run here. A breakpoint pauses when its line is reached,
+    // not again for every statement on that line.
+    line: usize,
+}
+
+impl Frame {
+    fn new(name: String) -> Self {
+        Frame {
+            name,
+            position: Position::default(),
+            env: None,
+            line: 0,
+        }
+    }
+}
+
+/// What a frontend can see and change while the program is paused.
+#[derive(Debug)]
+pub struct Session {
+    /// Lines to pause on, counted from 1.
+    pub breakpoints: BTreeSet<usize>,
+    // The active calls, innermost last.
+    frames: Vec<Frame>,
+    integer_mode: IntegerMode,
+    limits: Limits,
+}
+
+impl Session {
+    /// The active calls, innermost first. The last frame is the program itself.
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter().rev()
+    }
+
+    /// Evaluates `source` in `env` without pausing in any function it calls. Only the
+    /// source's own parse and resolve errors are an `Err`; errors it raises as it runs are
+    /// its value.
+    pub fn evaluate(&self, source: &str, env: &Rc<RefCell<Environment>>) -> Result<Object, Error> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let mut program = parser.parse();
+        if !parser.errors.is_empty() {
+            return Err(Error::Parse(parser.errors));
+        }
+        let mut errors = Resolver::with_integer_mode(self.integer_mode)
+            .resolve_in(&mut program, env)
+            .diagnostics;
+        errors.retain(Diagnostic::is_error);
+        if !errors.is_empty() {
+            return Err(Error::Resolve(errors));
+        }
+
+        let mut evaluator = Evaluator::new(self.integer_mode);
+        evaluator.limits = Limits {
+            steps: self.limits.steps.or(Some(EVALUATE_STEPS)),
+            ..self.limits
+        };
+        Ok(evaluator.eval_program(&program, env))
+    }
+}
+
+/// Something that decides what to do each time the program pauses, such as a person at a
+/// terminal.
+pub trait Frontend {
+    fn paused(&mut self, session: &mut Session, reason: Reason) -> Resume;
+}
+
+/// Pauses a program at breakpoints and after steps, handing control to a frontend each time.
+/// It starts paused before the first statement.
+///
+/// Install it as the evaluator's hook.
+pub struct Debugger<F> {
+    state: RefCell<State<F>>,
+}
+
+struct State<F> {
+    frontend: F,
+    session: Session,
+    resume: Option<Resume>,
+    // How many frames were active at the last pause.
+    depth: usize,
+}
+
+impl<F: Frontend> Debugger<F> {
+    /// Expressions evaluated at a pause run in `integer_mode` and within `limits`, as the
+    /// program does.
+    pub fn new(frontend: F, integer_mode: IntegerMode, limits: Limits) -> Self {
+        Debugger {
+            state: RefCell::new(State {
+                frontend,
+                session: Session {
+                    breakpoints: BTreeSet::new(),
+                    frames: vec![Frame::new(String::from("<main>"))],
+                    integer_mode,
+                    limits,
+                },
+                resume: None,
+                depth: 1,
+            }),
+        }
+    }
+
+    pub fn into_frontend(self) -> F {
+        self.state.into_inner().frontend
+    }
+}
+
+impl<F: Frontend> Hook for Debugger<F> {
+    fn statement(
+        &self,
+        statement: StatementId,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), String> {
+        let state = &mut *self.state.borrow_mut();
+        let position = ast.statement_token(statement).span.start;
+        let depth = state.session.frames.len();
+        let frame = state
+            .session
+            .frames
+            .last_mut()
+            .expect("the program's own frame is never popped");
+        let new_line = mem::replace(&mut frame.line, position.line) != position.line;
+        frame.position = position;
+        frame.env = Some(Rc::clone(env));
+
+        let reason = match state.resume {
+            Some(Resume::Quit) => return Err(String::from(STOPPED)),
+            None => Reason::Entry,
+            _ if new_line && state.session.breakpoints.contains(&position.line) => {
+                Reason::Breakpoint
+            }
+            Some(Resume::StepIn) => Reason::Step,
+            Some(Resume::StepOver) if depth <= state.depth => Reason::Step,
+            Some(Resume::StepOut) if depth < state.depth => Reason::Step,
+            _ => return Ok(()),
+        };
+
+        let resume = state.frontend.paused(&mut state.session, reason);
+        state.resume = Some(resume);
+        state.depth = depth;
+        match resume {
+            Resume::Quit => Err(String::from(STOPPED)),
+            _ => Ok(()),
+        }
+    }
+
+    fn enter(&self, call: &CallExpression, ast: &Rc<Ast>, _function: &Object) {
+        let frames = &mut self.state.borrow_mut().session.frames;
+        if let Some(caller) = frames.last_mut() {
+            caller.position = ast.expression_token(call.function).span.start;
+        }
+        frames.push(Frame::new(ast[call.function].string(ast)));
+    }
+
+    fn exit(&self, _result: &Object) {
+        self.state.borrow_mut().session.frames.pop();
+    }
+}
+
+/// Runs `source` under the debugger, taking commands from `input`.
+pub fn run(
+    source: &str,
+    integer_mode: IntegerMode,
+    limits: Limits,
+    input: impl BufRead + 'static,
+    mut output: impl Write + 'static,
+) -> io::Result<()> {
+    let mut interpreter = Interpreter::new(integer_mode);
+    interpreter.set_limits(limits);
+    let program = match interpreter.compile(source) {
+        Ok((program, warnings)) => {
+            for warning in &warnings {
+                writeln!(output, "{}", warning)?;
+            }
+            program
+        }
+        Err(error) => return writeln!(output, "{}", error),
+    };
+
+    let console = Console {
+        input,
+        output,
+        lines: source.lines().map(String::from).collect(),
+        quit: false,
+        error: None,
+    };
+    let debugger = Debugger::new(console, integer_mode, limits);
+    let (result, debugger) = interpreter.run_hooked(&program, debugger);
+    let mut console = debugger.into_frontend();
+    if let Some(error) = console.error {
+        return Err(error);
+    }
+    if !console.quit {
+        writeln!(console.output, "Program finished: {}", result)?;
+    }
+    Ok(())
+}
+
+const HELP: &str = "\
+break LINE   pause when LINE is reached (b)
+delete LINE  remove the breakpoint on LINE (d)
+continue     run to the next breakpoint (c)
+step         run to the next statement, entering calls (s)
+next         run to the next statement, stepping over calls (n)
+finish       run until the current call returns (f)
+print EXPR   evaluate EXPR in the paused scope (p)
+vars         list the bindings in scope (v)
+backtrace    list the active calls (bt)
+quit         stop the program (q)";
+
+// Takes commands a line at a time, from a person at a terminal or a test.
+struct Console<R, W> {
+    input: R,
+    output: W,
+    lines: Vec<String>,
+    quit: bool,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead, W: Write> Frontend for Console<R, W> {
+    fn paused(&mut self, session: &mut Session, reason: Reason) -> Resume {
+        match self.prompt(session, reason) {
+            Ok(resume) => {
+                self.quit = resume == Resume::Quit;
+                resume
+            }
+            Err(error) => {
+                self.error = Some(error);
+                Resume::Quit
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    fn prompt(&mut self, session: &mut Session, reason: Reason) -> io::Result<Resume> {
+        let frame = session.frames().next().expect("there is always a frame");
+        let line = self.lines.get(frame.position.line - 1);
+        writeln!(
+            self.output,
+            "{} at {} in {}",
+            if reason == Reason::Breakpoint {
+                "Breakpoint"
+            } else {
+                "Paused"
+            },
+            frame.position,
+            frame.name
+        )?;
+        writeln!(
+            self.output,
+            "{:>4} | {}",
+            frame.position.line,
+            line.map_or("", |line| line.trim_end())
+        )?;
+
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(Resume::Quit);
+            }
+            let line = line.trim();
+            let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let argument = argument.trim();
+
+            match command {
+                "" => {}
+                "break" | "b" => match argument.parse() {
+                    Ok(line) if line > 0 => {
+                        session.breakpoints.insert(line);
+                        writeln!(self.output, "Breakpoint set on line {}", line)?;
+                    }
+                    _ => writeln!(self.output, "usage: break LINE")?,
+                },
+                "delete" | "d" => match argument.parse() {
+                    Ok(line) if session.breakpoints.remove(&line) => {
+                        writeln!(self.output, "Breakpoint on line {} deleted", line)?;
+                    }
+                    _ => writeln!(self.output, "no breakpoint on line {}", argument)?,
+                },
+                "continue" | "c" => return Ok(Resume::Continue),
+                "step" | "s" => return Ok(Resume::StepIn),
+                "next" | "n" => return Ok(Resume::StepOver),
+                "finish" | "f" => return Ok(Resume::StepOut),
+                "quit" | "q" => return Ok(Resume::Quit),
+                "print" | "p" => self.print(session, argument)?,
+                "vars" | "v" => self.vars(session)?,
+                "backtrace" | "bt" => {
+                    for (index, frame) in session.frames().enumerate() {
+                        writeln!(
+                            self.output,
+                            "#{} {} at {}",
+                            index, frame.name, frame.position
+                        )?;
+                    }
+                }
+                "help" | "h" => writeln!(self.output, "{}", HELP)?,
+                _ => writeln!(self.output, "unknown command: {} (try `help`)", command)?,
+            }
+        }
+    }
+
+    fn print(&mut self, session: &Session, source: &str) -> io::Result<()> {
+        let Some(env) = paused_scope(session) else {
+            return Ok(());
+        };
+        match session.evaluate(source, env) {
+            Ok(value) => writeln!(self.output, "{}", value),
+            Err(error) => writeln!(self.output, "{}", error),
+        }
+    }
+
+    // Innermost scope first. A name shadowed by an inner binding is listed only once.
+    fn vars(&mut self, session: &Session) -> io::Result<()> {
+        let mut seen: HashSet<Symbol> = HashSet::new();
+        let mut scope = paused_scope(session).cloned();
+        while let Some(env) = scope {
+            let env = env.borrow();
+            for (name, value) in env.bindings() {
+                if seen.insert(name) {
+                    writeln!(self.output, "{} = {}", name, value)?;
+                }
+            }
+            scope = env.outer().cloned();
+        }
+        Ok(())
+    }
+}
+
+fn paused_scope(session: &Session) -> Option<&Rc<RefCell<Environment>>> {
+    session.frames().next()?.env.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Output the test can still read after `run` has taken ownership of the writer.
+    #[derive(Clone, Default)]
+    struct Transcript(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Transcript {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn debug(source: &str, commands: &'static str) -> String {
+        debug_within(source, Limits::default(), commands)
+    }
+
+    fn debug_within(source: &str, limits: Limits, commands: &'static str) -> String {
+        let transcript = Transcript::default();
+        run(
+            source,
+            IntegerMode::default(),
+            limits,
+            commands.as_bytes(),
+            transcript.clone(),
+        )
+        .unwrap();
+        let output = transcript.0.borrow();
+        String::from_utf8(output.clone()).unwrap()
+    }
+
+    const PROGRAM: &str = "let add = fn(a, b) {
+  let sum = a + b;
+  sum
+};
+let x = add(1, 2);
+let y = add(x, 10);
+y";
+
+    #[test]
+    fn test_breakpoints_and_inspection() {
+        let output = debug(PROGRAM, "b 2\nc\nbt\np a * 10 + b\nv\nc\np sum\nd 2\nc\n");
+        assert_eq!(
+            output,
+            "Paused at 1:1 in <main>
+   1 | let add = fn(a, b) {
+(debug) Breakpoint set on line 2
+(debug) Breakpoint at 2:3 in add
+   2 |   let sum = a + b;
+(debug) #0 add at 2:3
+#1 <main> at 5:9
+(debug) 12
+(debug) a = 1
+b = 2
+add = fn(a, b) { let sum = (a + b);sum }
+(debug) Breakpoint at 2:3 in add
+   2 |   let sum = a + b;
+(debug) 1:1: undefined identifier: sum
+(debug) Breakpoint on line 2 deleted
+(debug) Program finished: 13
+"
+        );
+    }
+
+    #[test]
+    fn test_stepping() {
+        let output = debug(PROGRAM, "n\nn\ns\ns\nf\nq\n");
+        let pauses: Vec<&str> = output
+            .lines()
+            .map(|line| line.trim_start_matches("(debug) "))
+            .filter(|line| line.starts_with("Paused"))
+            .collect();
+        assert_eq!(
+            pauses,
+            [
+                "Paused at 1:1 in <main>",
+                "Paused at 5:1 in <main>",
+                "Paused at 6:1 in <main>",
+                "Paused at 2:3 in add",
+                "Paused at 3:3 in add",
+                "Paused at 7:1 in <main>",
+            ]
+        );
+        assert!(!output.contains("Program finished"));
+    }
+
+    #[test]
+    fn test_quit_stops_the_program() {
+        let output = debug("let a = 1;\nlet b = 2;\na + b", "q\n");
+        assert_eq!(
+            output,
+            "Paused at 1:1 in <main>\n   1 | let a = 1;\n(debug) "
+        );
+
+        // Running out of commands quits too.
+        let output = debug("let a = 1;\nlet b = 2;\na + b", "s\n");
+        assert!(output.ends_with("   2 | let b = 2;\n(debug) "));
+    }
+
+    #[test]
+    fn test_printing_is_limited() {
+        let limits = Limits {
+            steps: Some(1000),
+            ..Limits::default()
+        };
+        let output = debug_within("let x = 1;\nx", limits, "p while (true) { }\nq\n");
+        assert!(
+            output.contains("(debug) LIMIT EXCEEDED: took more than 1000 steps\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_errors_before_running() {
+        assert_eq!(
+            debug("let = 1;", ""),
+            "1:5: expected next token to be IDENTIFIER, got = instead\n"
+        );
+        assert_eq!(debug("x", ""), "1:1: undefined identifier: x\n");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::ast::{Ast, Program};
+use crate::lexer::{Cursor, Lexer};
+use crate::parser::{ParseError, Parser, TopLevel};
+use crate::token::{Position, Span};
+use std::mem;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Source text that stays parsed as it is edited.
+///
+/// An edit is reparsed from shortly before it up to the first top-level statement that ends
+/// where one ended before the edit. The statements after that are reused as they are, only
+/// moved to where the edit left their text.
+#[derive(Debug)]
+pub struct Document {
+    text: String,
+    ast: Rc<Ast>,
+    items: Vec<Item>,
+    // The size of the arena after the last full parse. Statements an edit replaces are left
+    // in the arena, so once it has grown well past this the whole text is parsed again.
+    parsed_nodes: usize,
+}
+
+// A top-level statement, or the tokens skipped over after one that didn't parse.
+#[derive(Debug)]
+struct Item {
+    range: Range<usize>,
+    top_level: TopLevel,
+}
+
+impl Document {
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let mut parser = Parser::new(Lexer::new(&text));
+        let mut cursor = Cursor::new(&text);
+        let mut items = Vec::new();
+        while let Some(top_level) = parser.parse_top_level() {
+            items.push(Item::new(&mut cursor, top_level));
+        }
+        let ast = parser.into_ast();
+
+        Document {
+            parsed_nodes: ast.node_count(),
+            ast: Rc::new(ast),
+            items,
+            text,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn program(&self) -> Program {
+        Program {
+            ast: Rc::clone(&self.ast),
+            statements: self
+                .items
+                .iter()
+                .filter_map(|item| item.top_level.statement)
+                .collect(),
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ParseError> {
+        self.items.iter().flat_map(|item| &item.top_level.errors)
+    }
+
+    /// Replaces the bytes in `range` with `replacement` and brings the parse up to date.
+    ///
+    /// # Panics
+    ///
+    /// If either end of `range` is past the end of the text or not on a char boundary.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) {
+        // The parser looks one token past the end of a statement to see that it has ended, so
+        // the statement before the first one the edit touches is parsed again too.
+        let touched = self
+            .items
+            .partition_point(|item| item.range.end < range.start);
+        let kept = touched.saturating_sub(1);
+        let (offset, position) = match kept.checked_sub(1) {
+            Some(last) => (
+                self.items[last].range.end,
+                self.items[last].top_level.span.end,
+            ),
+            None => (0, Position::default()),
+        };
+
+        let mut cursor = Cursor::resume(&self.text, offset, position);
+        let start = cursor.advance_to_offset(range.start);
+        let old_end = cursor.advance_to_offset(range.end);
+        self.text.replace_range(range.clone(), replacement);
+        let new_end_offset = range.start + replacement.len();
+        let new_end =
+            Cursor::resume(&self.text, range.start, start).advance_to_offset(new_end_offset);
+
+        // Where the text after the edit has moved to.
+        let moved_offset = |offset: usize| offset - range.end + new_end_offset;
+        let moved = |position: Position| {
+            if position.line == old_end.line {
+                Position {
+                    line: new_end.line,
+                    column: position.column - old_end.column + new_end.column,
+                }
+            } else {
+                Position {
+                    line: position.line + new_end.line - old_end.line,
+                    column: position.column,
+                }
+            }
+        };
+
+        let mut old = self.items.split_off(kept).into_iter().peekable();
+        let ast = mem::take(Rc::make_mut(&mut self.ast));
+        let mut parser = Parser::resume(Lexer::resume(&self.text, offset, position), ast);
+        let mut cursor = Cursor::resume(&self.text, offset, position);
+        let mut lined_up = false;
+
+        while let Some(top_level) = parser.parse_top_level() {
+            let item = Item::new(&mut cursor, top_level);
+            let end = item.range.end;
+            self.items.push(item);
+            if end < new_end_offset {
+                continue;
+            }
+            while old
+                .next_if(|item| item.range.end < range.end || moved_offset(item.range.end) < end)
+                .is_some()
+            {}
+            if old
+                .next_if(|item| moved_offset(item.range.end) == end)
+                .is_some()
+            {
+                lined_up = true;
+                break;
+            }
+        }
+        let mut ast = parser.into_ast();
+
+        if lined_up {
+            // Moving is a no-op for statements wholly below an edit that kept the line count.
+            let unmoved_from = (new_end.line == old_end.line).then_some(old_end.line + 1);
+            for mut item in old {
+                item.range = moved_offset(item.range.start)..moved_offset(item.range.end);
+                if unmoved_from.is_some_and(|line| item.top_level.span.start.line >= line) {
+                    self.items.push(item);
+                    continue;
+                }
+                let top_level = &mut item.top_level;
+                top_level.span = Span {
+                    start: moved(top_level.span.start),
+                    end: moved(top_level.span.end),
+                };
+                for error in &mut top_level.errors {
+                    let token = error.token_mut();
+                    token.span = Span {
+                        start: moved(token.span.start),
+                        end: moved(token.span.end),
+                    };
+                }
+                if let Some(statement) = top_level.statement {
+                    ast.map_positions(statement, &mut |position| moved(position));
+                }
+                self.items.push(item);
+            }
+        }
+        self.ast = Rc::new(ast);
+
+        if self.ast.node_count() > 2 * self.parsed_nodes + 1024 {
+            *self = Document::new(mem::take(&mut self.text));
+        }
+    }
+}
+
+impl Item {
+    fn new(cursor: &mut Cursor, top_level: TopLevel) -> Self {
+        let start = cursor.advance_to(top_level.span.start);
+        let end = cursor.advance_to(top_level.span.end);
+        Item {
+            range: start..end,
+            top_level,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Everything a fresh parse of the document's text would give, positions included.
+    fn snapshot(document: &Document) -> (String, Vec<String>, Vec<Position>) {
+        let program = document.program();
+        let errors = document.errors().map(|error| error.to_string()).collect();
+        let mut ast = (*program.ast).clone();
+        let mut positions = Vec::new();
+        for &statement in &program.statements {
+            ast.map_positions(statement, &mut |position| {
+                positions.push(position);
+                position
+            });
+        }
+        (program.string(), errors, positions)
+    }
+
+    fn assert_reparsed(document: &Document) {
+        let fresh = Document::new(document.text());
+        assert_eq!(
+            snapshot(document),
+            snapshot(&fresh),
+            "after edits the text is {:?}",
+            document.text()
+        );
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut document = Document::new("let a = 1;\nlet b = 2;\nlet c = a + b;");
+
+        document.edit(19..20, "20");
+        assert_eq!(document.text(), "let a = 1;\nlet b = 20;\nlet c = a + b;");
+        assert_reparsed(&document);
+
+        document.edit(0..0, "let z = 0;\n\n");
+        assert_reparsed(&document);
+
+        // Dropping the `=` of `let b = 20;` breaks that statement alone.
+        document.edit(29..30, "");
+        assert_eq!(document.errors().count(), 1);
+        assert_reparsed(&document);
+
+        document.edit(29..29, "=");
+        assert_eq!(document.errors().count(), 0);
+        assert_reparsed(&document);
+    }
+
+    #[test]
+    fn test_edit_reuses_later_statements() {
+        let mut document = Document::new("let a = 1;\nlet b = 2;\nlet c = 3;\nlet d = 4;");
+        let before = document.program().statements;
+
+        // Only `let c` and the statement before it are parsed again.
+        document.edit(30..30, "0 + ");
+        assert_eq!(
+            document.text(),
+            "let a = 1;\nlet b = 2;\nlet c = 0 + 3;\nlet d = 4;"
+        );
+        let after = document.program().statements;
+        assert_eq!(after[0], before[0]);
+        assert_ne!(after[1..3], before[1..3]);
+        assert_eq!(after[3], before[3]);
+        assert_reparsed(&document);
+
+        // Edits that join or split statements reparse until the statements line up again.
+        document.edit(9..11, " ");
+        assert_reparsed(&document);
+        document.edit(document.text().len()..document.text().len(), " + a");
+        assert_reparsed(&document);
+    }
+
+    // A small xorshift generator, so the edits are random but the same on every run.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn boundary(&mut self, text: &str) -> usize {
+            let mut offset = self.below(text.len() + 1);
+            while !text.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            offset
+        }
+    }
+
+    #[test]
+    fn test_random_edits_match_a_full_reparse() {
+        let fragments = [
+            "",
+            " ",
+            "\n",
+            "let ",
+            "x",
+            "y = ",
+            "1",
+            "é",
+            ";",
+            "(",
+            ")",
+            "{",
+            "}",
+            "[",
+            ",",
+            "+",
+            "==",
+            "=",
+            "fn(a) { a }",
+            "if (x) { 1 } else { 2 }",
+            "while (x) { break; }",
+            "\"s\"",
+            "\"",
+            "// c\n",
+            "return ",
+        ];
+        let source = "let add = fn(a, b) {\n  a + b\n};\nlet x = add(1, 2);\n\
+                      // sum\nlet y = [x, \"é\"];\nwhile (x < 10) { x = x + 1; }\n\
+                      if (y) { x } else { add(x, x) }\n";
+
+        for seed in 1..=40u64 {
+            let mut random = Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut document = Document::new(source);
+            for _ in 0..60 {
+                let start = random.boundary(document.text());
+                let end = start + random.boundary(&document.text()[start..]) / 4;
+                let end = (end..=document.text().len())
+                    .find(|&end| document.text().is_char_boundary(end))
+                    .unwrap();
+                let replacement = fragments[random.below(fragments.len())];
+                document.edit(start..end, replacement);
+                assert_reparsed(&document);
+            }
+        }
+    }
+}
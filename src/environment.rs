@@ -0,0 +1,157 @@
+use crate::object::{Object, Teardown};
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A scope at run time. The global scope binds names; the scopes of calls and loop
+/// iterations only have the slots the resolver gave their locals.
+#[derive(Debug, Default)]
+pub struct Environment {
+    /// Globals, by name. Only the global scope has any.
+    globals: HashMap<Symbol, Object>,
+    /// Locals the resolver gave a slot, with their names. A slot stays `None` until the
+    /// declaration that fills it has run.
+    slots: Vec<Option<(Symbol, Object)>>,
+    outer: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    pub fn new_enclosed(outer: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            globals: HashMap::new(),
+            slots: Vec::new(),
+            outer: Some(outer),
+        }
+    }
+
+    /// The global scope `env` is in, which is `env` itself at the top level.
+    pub fn global(env: &Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+        let mut env = Rc::clone(env);
+        loop {
+            let outer = env.borrow().outer.clone();
+            match outer {
+                Some(outer) => env = outer,
+                None => return env,
+            }
+        }
+    }
+
+    /// Binds a global. Only called on the global scope.
+    pub fn set(&mut self, name: Symbol, value: Object) {
+        self.globals.insert(name, value);
+    }
+
+    /// The bindings made in this scope itself, sorted by name.
+    pub fn bindings(&self) -> Vec<(Symbol, Object)> {
+        let mut bindings: Vec<(Symbol, Object)> = self
+            .globals
+            .iter()
+            .map(|(name, value)| (*name, value.clone()))
+            .chain(self.slots.iter().flatten().cloned())
+            .collect();
+        bindings.sort_by_key(|(name, _)| name.as_str());
+        bindings
+    }
+
+    /// Every value bound in this scope itself.
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.globals
+            .values()
+            .chain(self.slots.iter().flatten().map(|(_, value)| value))
+    }
+
+    pub fn outer(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.outer.as_ref()
+    }
+
+    /// The globals bound so far, if this is the global scope.
+    pub fn global_names(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.globals.keys().copied()
+    }
+
+    /// The locals bound so far, with their slots.
+    pub fn local_names(&self) -> impl Iterator<Item = (usize, Symbol)> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, local)| Some((slot, local.as_ref()?.0)))
+    }
+
+    /// One past the highest slot given out so far.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn get_local(&self, depth: usize, slot: usize) -> Option<Object> {
+        if depth > 0 {
+            return self.outer.as_ref()?.borrow().get_local(depth - 1, slot);
+        }
+        let (_, value) = self.slots.get(slot)?.as_ref()?;
+        Some(value.clone())
+    }
+
+    pub fn set_local(&mut self, slot: usize, name: Symbol, value: Object) {
+        if self.slots.len() <= slot {
+            self.slots.resize(slot + 1, None);
+        }
+        self.slots[slot] = Some((name, value));
+    }
+
+    /// Rebinds a local the resolver has found. Returns `false` if its declaration hasn't
+    /// run yet.
+    pub fn assign_local(&mut self, depth: usize, slot: usize, value: Object) -> bool {
+        if depth > 0 {
+            return match &self.outer {
+                Some(outer) => outer.borrow_mut().assign_local(depth - 1, slot, value),
+                None => false,
+            };
+        }
+        match self.slots.get_mut(slot) {
+            Some(Some((_, current))) => {
+                *current = value;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves everything this scope holds onto `teardown`.
+    pub fn empty_into(&mut self, teardown: &mut Teardown) {
+        teardown
+            .values
+            .extend(self.globals.drain().map(|(_, value)| value));
+        teardown
+            .values
+            .extend(self.slots.drain(..).flatten().map(|(_, value)| value));
+        teardown.environments.extend(self.outer.take());
+    }
+
+    /// Looks up a global. Only called on the global scope.
+    pub fn get_global(&self, name: Symbol) -> Option<Object> {
+        self.globals.get(&name).cloned()
+    }
+
+    /// Rebinds a global. Returns `false` if it isn't bound. Only called on the global scope.
+    pub fn assign_global(&mut self, name: Symbol, value: Object) -> bool {
+        match self.globals.get_mut(&name) {
+            Some(current) => {
+                *current = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        let mut teardown = Teardown::default();
+        self.empty_into(&mut teardown);
+        teardown.run();
+    }
+}
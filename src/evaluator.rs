@@ -0,0 +1,1967 @@
+use crate::ast::*;
+use crate::environment::Environment;
+use crate::gc::{Heap, HeapStats};
+use crate::limits::{Limit, Limits};
+use crate::object::{Builtin, Function, Object, TailCall};
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenKind};
+use num_bigint::BigInt;
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Debug};
+use std::mem;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// How integer arithmetic behaves when a result doesn't fit in an `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IntegerMode {
+    /// Overflow promotes the result to an arbitrary-precision integer.
+    #[default]
+    Arbitrary,
+    /// Overflow produces an error value.
+    Checked,
+    /// Overflow wraps around in two's complement.
+    Wrapping,
+}
+
+/// Watches a program as it runs, for debuggers and profilers.
+pub trait Hook {
+    /// Called before each statement runs in `env`. An `Err` stops the program with that
+    /// message as its error.
+    fn statement(
+        &self,
+        statement: StatementId,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), String>;
+
+    /// Called once a call's arguments have been evaluated, before `function` is applied.
+    fn enter(&self, _call: &CallExpression, _ast: &Rc<Ast>, _function: &Object) {}
+
+    /// Called when the call last entered returns, with what it returned.
+    fn exit(&self, _result: &Object) {}
+
+    /// Called when the call last entered makes a tail call, which replaces it, before the
+    /// callee is entered. By default the replaced call exits as though it returned nothing.
+    fn tail_call(&self) {
+        self.exit(&Object::Null);
+    }
+}
+
+#[derive(Default)]
+pub struct Evaluator {
+    pub integer_mode: IntegerMode,
+    pub hook: Option<Rc<dyn Hook>>,
+    pub limits: Limits,
+    heap: RefCell<Heap>,
+    // Usage of the program running, to hold against `limits`.
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    started: Cell<Option<Instant>>,
+    // Where the native stack was when the program started running.
+    stack_base: Cell<Option<usize>>,
+    // The global scope of the program running, where globals are looked up directly.
+    globals: RefCell<Option<Rc<RefCell<Environment>>>>,
+}
+
+impl Debug for Evaluator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Evaluator")
+            .field("integer_mode", &self.integer_mode)
+            .field("hook", &self.hook.is_some())
+            .field("limits", &self.limits)
+            .field("heap", &self.heap.borrow())
+            .finish()
+    }
+}
+
+impl Evaluator {
+    pub fn new(integer_mode: IntegerMode) -> Self {
+        Evaluator {
+            integer_mode,
+            hook: None,
+            limits: Limits::default(),
+            heap: RefCell::default(),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            started: Cell::new(None),
+            stack_base: Cell::new(None),
+            globals: RefCell::new(None),
+        }
+    }
+
+    /// A global scope for programs to run in. Unlike one made directly, it is collected
+    /// along with the functions it holds once the caller drops it.
+    pub fn new_environment(&self) -> Rc<RefCell<Environment>> {
+        self.heap.borrow_mut().environment(Environment::new())
+    }
+
+    /// Frees the values only reachable through reference cycles, and returns how many
+    /// environments and arrays that was.
+    pub fn collect_garbage(&self) -> usize {
+        self.heap.borrow_mut().collect()
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.borrow().stats()
+    }
+
+    /// Runs `program` in `env`. Each run gets the whole of `limits`.
+    pub fn eval_program(&self, program: &Program, env: &Rc<RefCell<Environment>>) -> Object {
+        self.run(Some(Environment::global(env)), || {
+            let mut result = Object::Null;
+            for statement in &program.statements {
+                result = self.eval_statement(*statement, &program.ast, env, false);
+
+                match result {
+                    Object::ReturnValue(value) => {
+                        return match *value {
+                            Object::TailCall(call) => self.call(*call),
+                            value => value,
+                        }
+                    }
+                    Object::Error(_) | Object::LimitExceeded(_) => return result,
+                    _ => {}
+                }
+            }
+            result
+        })
+    }
+
+    /// Calls `function` from outside any program, as a host embedding the language does.
+    /// Each call gets the whole of `limits`.
+    pub fn call_function(&self, function: Object, arguments: Vec<Object>) -> Object {
+        let arity = match &function {
+            Object::Function(function) => function.parameters.len(),
+            Object::Builtin(builtin) => builtin.arity(),
+            other => return Object::Error(format!("not a function: {}", other.type_name())),
+        };
+        if arity != arguments.len() {
+            return Object::Error(format!(
+                "wrong number of arguments: want={}, got={}",
+                arity,
+                arguments.len()
+            ));
+        }
+
+        let globals = match &function {
+            Object::Function(function) => Some(Environment::global(&function.env)),
+            _ => None,
+        };
+        self.run(globals, || {
+            // With the checks above, nothing is reported at this token.
+            let token = Token::new(TokenKind::IDENTIFIER, "");
+            match self.apply_function(&token, function, arguments) {
+                Object::TailCall(call) => self.call(*call),
+                result => result,
+            }
+        })
+    }
+
+    // Runs `f` with fresh limits and `globals` as the global scope. The scope is only held
+    // for the run, so it can be collected once the caller drops it.
+    fn run(&self, globals: Option<Rc<RefCell<Environment>>>, f: impl FnOnce() -> Object) -> Object {
+        self.steps.set(0);
+        self.started.set(Some(Instant::now()));
+        self.stack_base.set(Some(stack_position()));
+        let previous = self.globals.replace(globals);
+        let result = f();
+        self.globals.replace(previous);
+        result
+    }
+
+    fn global(&self, name: Symbol) -> Option<Object> {
+        self.globals.borrow().as_ref()?.borrow().get_global(name)
+    }
+
+    // Counts a step of evaluation, and checks the limits that grow with every step. Every
+    // recursion in the evaluator goes through a step, so checking the stack here keeps any
+    // nesting from overflowing it.
+    fn step(&self) -> Result<(), Limit> {
+        if let Some(base) = self.stack_base.get() {
+            if base.abs_diff(stack_position()) > self.limits.stack {
+                return Err(Limit::Stack(self.limits.stack));
+            }
+        }
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(max) = self.limits.steps {
+            if steps > max {
+                return Err(Limit::Steps(max));
+            }
+        }
+        if let (Some(max), Some(started)) = (self.limits.time, self.started.get()) {
+            if started.elapsed() > max {
+                return Err(Limit::Time(max));
+            }
+        }
+        Ok(())
+    }
+
+    // Makes room on the heap for one more object. Collecting garbage only when the limit is
+    // reached keeps the common case cheap.
+    fn reserve(&self) -> Result<(), Limit> {
+        let Some(max) = self.limits.objects else {
+            return Ok(());
+        };
+        let mut heap = self.heap.borrow_mut();
+        if heap.tracked() >= max {
+            heap.collect();
+        }
+        if heap.tracked() >= max {
+            return Err(Limit::Objects(max));
+        }
+        Ok(())
+    }
+
+    // Checks a string or array of `bytes` may be built, before building it.
+    fn check_size(&self, bytes: usize) -> Result<(), Limit> {
+        if bytes > self.limits.size {
+            return Err(Limit::Size(self.limits.size));
+        }
+        Ok(())
+    }
+
+    // With `tail` set, a call the block ends with is returned as a `TailCall` rather than
+    // made.
+    fn eval_block_statement(
+        &self,
+        block: BlockId,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+        tail: bool,
+    ) -> Object {
+        let mut result = Object::Null;
+
+        let statements = &ast[block].statements;
+        for (index, statement) in statements.iter().enumerate() {
+            let tail = tail && index + 1 == statements.len();
+            result = self.eval_statement(*statement, ast, env, tail);
+
+            if matches!(
+                result,
+                Object::ReturnValue(_)
+                    | Object::Error(_)
+                    | Object::LimitExceeded(_)
+                    | Object::Break
+                    | Object::Continue
+            ) {
+                return result;
+            }
+        }
+        result
+    }
+
+    fn eval_statement(
+        &self,
+        id: StatementId,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+        tail: bool,
+    ) -> Object {
+        if let Err(limit) = self.step() {
+            return Object::LimitExceeded(limit);
+        }
+        if let Some(hook) = &self.hook {
+            if let Err(message) = hook.statement(id, ast, env) {
+                return Object::Error(message);
+            }
+        }
+        match ast.statement(id) {
+            StatementNode::ExpressionStatement(statement) => {
+                if tail {
+                    return self.eval_tail_expression(statement.expression, ast, env);
+                }
+                self.eval_expression(statement.expression, ast, env)
+            }
+            StatementNode::LetStatement(statement) => {
+                let value = self.eval_expression(statement.value, ast, env);
+                if value.is_unwinding() {
+                    return value;
+                }
+                declare(&statement.name, value, &mut env.borrow_mut());
+                Object::Null
+            }
+            StatementNode::ReturnStatement(statement) => {
+                // Whatever encloses it, a return statement is always in tail position.
+                let value = self.eval_tail_expression(statement.return_value, ast, env);
+                if value.is_unwinding() {
+                    return value;
+                }
+                Object::ReturnValue(Box::new(value))
+            }
+            StatementNode::WhileStatement(statement) => {
+                self.eval_while_statement(statement, ast, env)
+            }
+            StatementNode::ForStatement(statement) => self.eval_for_statement(statement, ast, env),
+            StatementNode::BreakStatement(_) => Object::Break,
+            StatementNode::ContinueStatement(_) => Object::Continue,
+        }
+    }
+
+    fn eval_while_statement(
+        &self,
+        statement: &WhileStatement,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        loop {
+            let condition = self.eval_expression(statement.condition, ast, env);
+            if condition.is_unwinding() {
+                return condition;
+            }
+            if !is_truthy(&condition) {
+                return Object::Null;
+            }
+
+            if let Err(limit) = self.reserve() {
+                return Object::LimitExceeded(limit);
+            }
+            let scope = self
+                .heap
+                .borrow_mut()
+                .environment(Environment::new_enclosed(Rc::clone(env)));
+            match self.eval_block_statement(statement.body, ast, &scope, false) {
+                Object::Break => return Object::Null,
+                result @ (Object::ReturnValue(_) | Object::Error(_) | Object::LimitExceeded(_)) => {
+                    return result
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn eval_for_statement(
+        &self,
+        statement: &ForStatement,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        let iterable = self.eval_expression(statement.iterable, ast, env);
+        // Iterate over a snapshot so the body can modify the array safely.
+        let elements = match iterable {
+            Object::Array(elements) => elements.borrow().clone(),
+            Object::String(value) => value.chars().map(|c| Object::String(c.into())).collect(),
+            iterable if iterable.is_unwinding() => return iterable,
+            other => {
+                return error(
+                    &statement.token,
+                    format!("cannot iterate over {}", other.type_name()),
+                )
+            }
+        };
+
+        for element in elements {
+            let mut scope = Environment::new_enclosed(Rc::clone(env));
+            declare(&statement.variable, element, &mut scope);
+
+            if let Err(limit) = self.reserve() {
+                return Object::LimitExceeded(limit);
+            }
+            let scope = self.heap.borrow_mut().environment(scope);
+            match self.eval_block_statement(statement.body, ast, &scope, false) {
+                Object::Break => break,
+                result @ (Object::ReturnValue(_) | Object::Error(_) | Object::LimitExceeded(_)) => {
+                    return result
+                }
+                _ => {}
+            }
+        }
+        Object::Null
+    }
+
+    fn eval_expression(
+        &self,
+        id: ExpressionId,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        if let Err(limit) = self.step() {
+            return Object::LimitExceeded(limit);
+        }
+        match ast.expression(id) {
+            ExpressionNode::IntegerLiteral(integer) => self.eval_integer_literal(integer),
+            ExpressionNode::Boolean(boolean) => Object::Boolean(boolean.value),
+            ExpressionNode::StringLiteral(string) => Object::String(string.value.clone()),
+            ExpressionNode::NullLiteral(_) => Object::Null,
+            ExpressionNode::Identifier(identifier) => self.eval_identifier(identifier, env),
+            ExpressionNode::PrefixExpression(prefix) => {
+                let right = self.eval_expression(prefix.right, ast, env);
+                if right.is_unwinding() {
+                    return right;
+                }
+                self.eval_prefix_expression(&prefix.token, &prefix.operator, right)
+            }
+            ExpressionNode::InfixExpression(infix) => {
+                let left = self.eval_expression(infix.left, ast, env);
+                if left.is_unwinding() {
+                    return left;
+                }
+                if infix.operator == "&&" || infix.operator == "||" {
+                    return self.eval_logical_expression(infix, left, ast, env);
+                }
+                if infix.operator == "??" {
+                    if matches!(left, Object::Null) {
+                        return self.eval_expression(infix.right, ast, env);
+                    }
+                    return left;
+                }
+                let right = self.eval_expression(infix.right, ast, env);
+                if right.is_unwinding() {
+                    return right;
+                }
+                self.eval_infix_expression(&infix.token, &infix.operator, left, right)
+            }
+            ExpressionNode::IfExpression(if_expression) => {
+                self.eval_if_expression(if_expression, ast, env, false)
+            }
+            ExpressionNode::FunctionLiteral(function) => Object::Function(Function {
+                parameters: function.parameters.clone(),
+                body: function.body,
+                ast: Rc::clone(ast),
+                env: Rc::clone(env),
+            }),
+            ExpressionNode::CallExpression(call) => {
+                match self.eval_call_operands(id, call, ast, env) {
+                    Ok(call) => self.call(call),
+                    Err(result) => result,
+                }
+            }
+            ExpressionNode::ArrayLiteral(array) => {
+                let bytes = array
+                    .elements
+                    .len()
+                    .saturating_mul(mem::size_of::<Object>());
+                if let Err(limit) = self.check_size(bytes) {
+                    return Object::LimitExceeded(limit);
+                }
+                let mut elements = Vec::with_capacity(array.elements.len());
+                for element in &array.elements {
+                    let value = self.eval_expression(*element, ast, env);
+                    if value.is_unwinding() {
+                        return value;
+                    }
+                    elements.push(value);
+                }
+                if let Err(limit) = self.reserve() {
+                    return Object::LimitExceeded(limit);
+                }
+                Object::Array(self.heap.borrow_mut().array(elements))
+            }
+            ExpressionNode::IndexExpression(index) => self
+                .eval_index(index, ast, env)
+                .unwrap_or_else(|result| result),
+            ExpressionNode::AssignExpression(assign) => {
+                self.eval_assign_expression(assign, ast, env)
+            }
+        }
+    }
+
+    // Evaluates an expression a function returns the value of. A call there is returned as
+    // a `TailCall`, so it is made after the function has returned and recursion through
+    // tail calls runs in constant stack space.
+    fn eval_tail_expression(
+        &self,
+        id: ExpressionId,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        match ast.expression(id) {
+            ExpressionNode::CallExpression(call) => {
+                match self.eval_call_operands(id, call, ast, env) {
+                    Ok(call) => Object::TailCall(Box::new(call)),
+                    Err(result) => result,
+                }
+            }
+            ExpressionNode::IfExpression(if_expression) => {
+                self.eval_if_expression(if_expression, ast, env, true)
+            }
+            _ => self.eval_expression(id, ast, env),
+        }
+    }
+
+    // Identifiers the resolver hasn't seen can only refer to globals.
+    fn eval_identifier(&self, identifier: &Identifier, env: &Rc<RefCell<Environment>>) -> Object {
+        let value = match identifier.binding {
+            Some(Binding::Local { depth, slot }) => env.borrow().get_local(depth, slot),
+            Some(Binding::Global) | None => self.global(identifier.value),
+        };
+        match value.or_else(|| Builtin::lookup(identifier.value).map(Object::Builtin)) {
+            Some(value) => value,
+            None => error(
+                &identifier.token,
+                format!("identifier not found: {}", identifier.value),
+            ),
+        }
+    }
+
+    pub fn eval_integer_literal(&self, integer: &IntegerLiteral) -> Object {
+        match i64::try_from(&integer.value) {
+            Ok(value) => Object::Integer(value),
+            Err(_) if self.integer_mode == IntegerMode::Arbitrary => {
+                Object::BigInteger(integer.value.clone())
+            }
+            Err(_) => error(
+                &integer.token,
+                format!(
+                    "integer literal {} is out of range for a 64-bit integer",
+                    integer.token.literal
+                ),
+            ),
+        }
+    }
+
+    // The right operand is only evaluated when the left one doesn't decide the result.
+    fn eval_logical_expression(
+        &self,
+        infix: &InfixExpression,
+        left: Object,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        let left = is_truthy(&left);
+        if (infix.operator == "&&") != left {
+            return Object::Boolean(left);
+        }
+
+        let right = self.eval_expression(infix.right, ast, env);
+        if right.is_unwinding() {
+            return right;
+        }
+        Object::Boolean(is_truthy(&right))
+    }
+
+    fn eval_assign_expression(
+        &self,
+        assign: &AssignExpression,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        match ast.expression(assign.target) {
+            ExpressionNode::Identifier(identifier) => {
+                let current = || self.eval_identifier(identifier, env);
+                let value = self.eval_assigned_value(assign, current, ast, env);
+                if value.is_unwinding() {
+                    return value;
+                }
+                let assigned = match identifier.binding {
+                    Some(Binding::Local { depth, slot }) => {
+                        env.borrow_mut().assign_local(depth, slot, value.clone())
+                    }
+                    Some(Binding::Global) | None => match &*self.globals.borrow() {
+                        Some(globals) => globals
+                            .borrow_mut()
+                            .assign_global(identifier.value, value.clone()),
+                        None => false,
+                    },
+                };
+                if !assigned {
+                    return error(
+                        &identifier.token,
+                        format!("assignment to undeclared variable: {}", identifier.value),
+                    );
+                }
+                value
+            }
+            ExpressionNode::IndexExpression(index) => {
+                let left = self.eval_expression(index.left, ast, env);
+                if left.is_unwinding() {
+                    return left;
+                }
+                let position = self.eval_expression(index.index, ast, env);
+                if position.is_unwinding() {
+                    return position;
+                }
+
+                let (elements, position) = match (left, position) {
+                    (Object::Array(elements), Object::Integer(position)) => (elements, position),
+                    (Object::Array(_), position) => {
+                        return error(
+                            &index.token,
+                            format!("array index must be INTEGER, got {}", position.type_name()),
+                        )
+                    }
+                    (left, _) => {
+                        return error(
+                            &index.token,
+                            format!("index assignment not supported: {}", left.type_name()),
+                        )
+                    }
+                };
+                let len = elements.borrow().len();
+                let slot = match usize::try_from(position) {
+                    Ok(slot) if slot < len => slot,
+                    _ => {
+                        return error(
+                            &index.token,
+                            format!("index out of range: {} (length {})", position, len),
+                        )
+                    }
+                };
+
+                let current = || elements.borrow()[slot].clone();
+                let value = self.eval_assigned_value(assign, current, ast, env);
+                if value.is_unwinding() {
+                    return value;
+                }
+                elements.borrow_mut()[slot] = value.clone();
+                value
+            }
+            // The parser only makes assignments to identifiers and indexes.
+            _ => error(
+                &assign.token,
+                format!(
+                    "invalid assignment target: {}",
+                    ast[assign.target].string(ast)
+                ),
+            ),
+        }
+    }
+
+    // Compound assignments read the current value before evaluating the right-hand side.
+    fn eval_assigned_value(
+        &self,
+        assign: &AssignExpression,
+        current: impl FnOnce() -> Object,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Object {
+        if assign.operator == "=" {
+            return self.eval_expression(assign.value, ast, env);
+        }
+
+        let current = current();
+        if current.is_unwinding() {
+            return current;
+        }
+        let value = self.eval_expression(assign.value, ast, env);
+        if value.is_unwinding() {
+            return value;
+        }
+        let operator = assign.operator.trim_end_matches('=');
+        self.eval_infix_expression(&assign.token, operator, current, value)
+    }
+
+    pub fn eval_prefix_expression(&self, token: &Token, operator: &str, right: Object) -> Object {
+        match (operator, right) {
+            ("!", right) => Object::Boolean(!is_truthy(&right)),
+            ("-", Object::Integer(value)) => match self.integer_mode {
+                IntegerMode::Arbitrary => match value.checked_neg() {
+                    Some(value) => Object::Integer(value),
+                    None => Object::BigInteger(-BigInt::from(value)),
+                },
+                IntegerMode::Checked => match value.checked_neg() {
+                    Some(value) => Object::Integer(value),
+                    None => error(token, format!("integer overflow: -({})", value)),
+                },
+                IntegerMode::Wrapping => Object::Integer(value.wrapping_neg()),
+            },
+            ("-", Object::BigInteger(value)) => Object::from_big_integer(-value),
+            ("~", Object::Integer(value)) => Object::Integer(!value),
+            ("~", Object::BigInteger(value)) => Object::from_big_integer(!value),
+            (operator, right) => error(
+                token,
+                format!("unknown operator: {}{}", operator, right.type_name()),
+            ),
+        }
+    }
+
+    pub fn eval_infix_expression(
+        &self,
+        token: &Token,
+        operator: &str,
+        left: Object,
+        right: Object,
+    ) -> Object {
+        match (left, right) {
+            (Object::Integer(left), Object::Integer(right)) => {
+                self.eval_integer_infix_expression(token, operator, left, right)
+            }
+            (Object::Integer(left), Object::BigInteger(right)) => {
+                self.eval_big_integer(token, operator, BigInt::from(left), right)
+            }
+            (Object::BigInteger(left), Object::Integer(right)) => {
+                self.eval_big_integer(token, operator, left, BigInt::from(right))
+            }
+            (Object::BigInteger(left), Object::BigInteger(right)) => {
+                self.eval_big_integer(token, operator, left, right)
+            }
+            (left @ Object::Null, right) | (left, right @ Object::Null)
+                if operator == "==" || operator == "!=" =>
+            {
+                let equal = matches!((left, right), (Object::Null, Object::Null));
+                Object::Boolean(equal == (operator == "=="))
+            }
+            (Object::String(left), Object::String(right)) => match operator {
+                "+" => match self.check_size(left.len().saturating_add(right.len())) {
+                    Ok(()) => Object::String(left + &right),
+                    Err(limit) => Object::LimitExceeded(limit),
+                },
+                "==" => Object::Boolean(left == right),
+                "!=" => Object::Boolean(left != right),
+                _ => error(
+                    token,
+                    format!("unknown operator: STRING {} STRING", operator),
+                ),
+            },
+            (Object::Boolean(left), Object::Boolean(right)) => match operator {
+                "==" => Object::Boolean(left == right),
+                "!=" => Object::Boolean(left != right),
+                _ => error(
+                    token,
+                    format!("unknown operator: BOOLEAN {} BOOLEAN", operator),
+                ),
+            },
+            (left, right) if left.type_name() == right.type_name() => error(
+                token,
+                format!(
+                    "unknown operator: {} {} {}",
+                    left.type_name(),
+                    operator,
+                    right.type_name()
+                ),
+            ),
+            (left, right) => error(
+                token,
+                format!(
+                    "type mismatch: {} {} {}",
+                    left.type_name(),
+                    operator,
+                    right.type_name()
+                ),
+            ),
+        }
+    }
+
+    fn eval_integer_infix_expression(
+        &self,
+        token: &Token,
+        operator: &str,
+        left: i64,
+        right: i64,
+    ) -> Object {
+        let result = match operator {
+            "+" => self.arithmetic(left, right, i64::checked_add, i64::wrapping_add),
+            "-" => self.arithmetic(left, right, i64::checked_sub, i64::wrapping_sub),
+            "*" => self.arithmetic(left, right, i64::checked_mul, i64::wrapping_mul),
+            "/" => {
+                if right == 0 {
+                    return error(token, String::from("division by zero"));
+                }
+                self.arithmetic(left, right, i64::checked_div, i64::wrapping_div)
+            }
+            "%" => {
+                if right == 0 {
+                    return error(token, String::from("division by zero"));
+                }
+                self.arithmetic(left, right, i64::checked_rem, i64::wrapping_rem)
+            }
+            "<" => return Object::Boolean(left < right),
+            ">" => return Object::Boolean(left > right),
+            "&" => return Object::Integer(left & right),
+            "|" => return Object::Integer(left | right),
+            "^" => return Object::Integer(left ^ right),
+            "<<" => {
+                if right < 0 {
+                    return error(token, format!("negative shift amount: {}", right));
+                }
+                self.arithmetic(left, right, checked_shl, wrapping_shl)
+            }
+            ">>" => {
+                if right < 0 {
+                    return error(token, format!("negative shift amount: {}", right));
+                }
+                return Object::Integer(left >> right.min(63));
+            }
+            "<=" => return Object::Boolean(left <= right),
+            ">=" => return Object::Boolean(left >= right),
+            "==" => return Object::Boolean(left == right),
+            "!=" => return Object::Boolean(left != right),
+            _ => {
+                return error(
+                    token,
+                    format!("unknown operator: INTEGER {} INTEGER", operator),
+                )
+            }
+        };
+
+        match result {
+            Some(value) => Object::Integer(value),
+            None if self.integer_mode == IntegerMode::Arbitrary => {
+                self.eval_big_integer(token, operator, BigInt::from(left), BigInt::from(right))
+            }
+            None => error(
+                token,
+                format!("integer overflow: {} {} {}", left, operator, right),
+            ),
+        }
+    }
+
+    // A single operation can make an arbitrary precision integer huge, so the size of the
+    // result is checked before it is computed.
+    fn eval_big_integer(
+        &self,
+        token: &Token,
+        operator: &str,
+        left: BigInt,
+        right: BigInt,
+    ) -> Object {
+        if let Some(max) = self.limits.integer_bits {
+            if result_bits(operator, &left, &right) > max {
+                return Object::LimitExceeded(Limit::IntegerBits(max));
+            }
+        }
+        eval_big_integer_infix_expression(token, operator, left, right)
+    }
+
+    fn arithmetic(
+        &self,
+        left: i64,
+        right: i64,
+        checked: fn(i64, i64) -> Option<i64>,
+        wrapping: fn(i64, i64) -> i64,
+    ) -> Option<i64> {
+        match self.integer_mode {
+            IntegerMode::Arbitrary | IntegerMode::Checked => checked(left, right),
+            IntegerMode::Wrapping => Some(wrapping(left, right)),
+        }
+    }
+
+    fn eval_if_expression(
+        &self,
+        if_expression: &IfExpression,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+        tail: bool,
+    ) -> Object {
+        let condition = self.eval_expression(if_expression.condition, ast, env);
+        if condition.is_unwinding() {
+            return condition;
+        }
+
+        if is_truthy(&condition) {
+            self.eval_block_statement(if_expression.consequence, ast, env, tail)
+        } else {
+            match &if_expression.alternative {
+                Some(ElseBranch::Block(alternative)) => {
+                    self.eval_block_statement(*alternative, ast, env, tail)
+                }
+                Some(ElseBranch::If(alternative)) if tail => {
+                    self.eval_tail_expression(*alternative, ast, env)
+                }
+                Some(ElseBranch::If(alternative)) => self.eval_expression(*alternative, ast, env),
+                None => Object::Null,
+            }
+        }
+    }
+
+    // Evaluates the link `id` of a chain of calls and indexes such as `a?.[0][1](x)`. `Err`
+    // holds the result of the whole chain when it is already known: a value that unwinds,
+    // or null once a `?.` link has found null, which skips every link after it.
+    fn eval_chain(
+        &self,
+        id: ExpressionId,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, Object> {
+        let value = match ast.expression(id) {
+            ExpressionNode::IndexExpression(index) => {
+                self.step().map_err(Object::LimitExceeded)?;
+                self.eval_index(index, ast, env)?
+            }
+            ExpressionNode::CallExpression(call) => {
+                self.step().map_err(Object::LimitExceeded)?;
+                self.call(self.eval_call_operands(id, call, ast, env)?)
+            }
+            _ => self.eval_expression(id, ast, env),
+        };
+        if value.is_unwinding() {
+            Err(value)
+        } else {
+            Ok(value)
+        }
+    }
+
+    // Indexes into the operand of `index`, or returns the result of the whole chain if that
+    // is already known.
+    fn eval_index(
+        &self,
+        index: &IndexExpression,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, Object> {
+        let left = self.eval_chain(index.left, ast, env)?;
+        if index.optional && matches!(left, Object::Null) {
+            return Err(Object::Null);
+        }
+        let position = self.eval_expression(index.index, ast, env);
+        if position.is_unwinding() {
+            return Err(position);
+        }
+        Ok(eval_index_expression(&index.token, left, position))
+    }
+
+    // Evaluates the function and arguments of the call `id`, or returns the result of the
+    // whole chain if that is already known.
+    fn eval_call_operands(
+        &self,
+        id: ExpressionId,
+        call: &CallExpression,
+        ast: &Rc<Ast>,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<TailCall, Object> {
+        let function = self.eval_chain(call.function, ast, env)?;
+        if call.optional && matches!(function, Object::Null) {
+            return Err(Object::Null);
+        }
+
+        let mut arguments = Vec::with_capacity(call.arguments.len());
+        for argument in &call.arguments {
+            let value = self.eval_expression(*argument, ast, env);
+            if value.is_unwinding() {
+                return Err(value);
+            }
+            arguments.push(value);
+        }
+        Ok(TailCall {
+            call: id,
+            ast: Rc::clone(ast),
+            function,
+            arguments,
+        })
+    }
+
+    // Makes `call`, and then each tail call it returns in turn. The hook sees every one of
+    // them as a nested call, so its frames still match the calls in the source.
+    fn call(&self, mut call: TailCall) -> Object {
+        let mut entered = false;
+        loop {
+            let TailCall {
+                call: id,
+                ast,
+                function,
+                arguments,
+            } = call;
+            let ExpressionNode::CallExpression(expression) = ast.expression(id) else {
+                unreachable!("tail calls are made by call expressions");
+            };
+            if let Some(hook) = &self.hook {
+                if entered {
+                    hook.tail_call();
+                }
+                hook.enter(expression, &ast, &function);
+                entered = true;
+            }
+
+            match self.apply_function(&expression.token, function, arguments) {
+                Object::TailCall(next) => call = *next,
+                result => {
+                    if let (Some(hook), true) = (&self.hook, entered) {
+                        hook.exit(&result);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn apply_function(&self, token: &Token, function: Object, arguments: Vec<Object>) -> Object {
+        let function = match function {
+            Object::Function(function) => function,
+            Object::Builtin(builtin) => return self.apply_builtin(token, builtin, arguments),
+            other => return error(token, format!("not a function: {}", other.type_name())),
+        };
+
+        if function.parameters.len() != arguments.len() {
+            return error(
+                token,
+                format!(
+                    "wrong number of arguments: want={}, got={}",
+                    function.parameters.len(),
+                    arguments.len()
+                ),
+            );
+        }
+
+        let mut extended = Environment::new_enclosed(Rc::clone(&function.env));
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            declare(parameter, argument, &mut extended);
+        }
+
+        let depth = self.depth.get();
+        if let Some(max) = self.limits.call_depth {
+            if depth >= max {
+                return Object::LimitExceeded(Limit::CallDepth(max));
+            }
+        }
+        if let Err(limit) = self.reserve() {
+            return Object::LimitExceeded(limit);
+        }
+        let extended = self.heap.borrow_mut().environment(extended);
+        self.depth.set(depth + 1);
+        let result = self.eval_block_statement(function.body, &function.ast, &extended, true);
+        self.depth.set(depth);
+        match result {
+            Object::ReturnValue(value) => *value,
+            result => result,
+        }
+    }
+
+    fn apply_builtin(&self, token: &Token, builtin: Builtin, arguments: Vec<Object>) -> Object {
+        match builtin {
+            Builtin::Gc => {
+                if !arguments.is_empty() {
+                    return error(
+                        token,
+                        format!("wrong number of arguments: want=0, got={}", arguments.len()),
+                    );
+                }
+                Object::Integer(self.collect_garbage() as i64)
+            }
+        }
+    }
+}
+
+// Shifting out any significant bit, including the sign, counts as overflow.
+fn checked_shl(left: i64, right: i64) -> Option<i64> {
+    if right >= 64 {
+        return (left == 0).then_some(0);
+    }
+    let shifted = left << right;
+    (shifted >> right == left).then_some(shifted)
+}
+
+fn wrapping_shl(left: i64, right: i64) -> i64 {
+    if right >= 64 {
+        0
+    } else {
+        left << right
+    }
+}
+
+// An upper bound on the bits in the result of `operator`.
+fn result_bits(operator: &str, left: &BigInt, right: &BigInt) -> u64 {
+    match operator {
+        "+" | "-" => left.bits().max(right.bits()) + 1,
+        "*" => left.bits() + right.bits(),
+        "<<" if left.bits() == 0 || *right < BigInt::ZERO => left.bits(),
+        "<<" => u64::try_from(right).map_or(u64::MAX, |amount| left.bits().saturating_add(amount)),
+        _ => left.bits().max(right.bits()),
+    }
+}
+
+// The furthest an arbitrary precision integer may be shifted left, which makes a result of a
+// few megabytes. Larger shifts are almost certainly mistakes, and could exhaust memory.
+const MAX_SHIFT: u32 = 1 << 24;
+
+// Only reached in `IntegerMode::Arbitrary`, since no other mode produces a `BigInteger`.
+fn eval_big_integer_infix_expression(
+    token: &Token,
+    operator: &str,
+    left: BigInt,
+    right: BigInt,
+) -> Object {
+    match operator {
+        "+" => Object::from_big_integer(left + right),
+        "-" => Object::from_big_integer(left - right),
+        "*" => Object::from_big_integer(left * right),
+        "/" => {
+            if right == BigInt::ZERO {
+                return error(token, String::from("division by zero"));
+            }
+            Object::from_big_integer(left / right)
+        }
+        "%" => {
+            if right == BigInt::ZERO {
+                return error(token, String::from("division by zero"));
+            }
+            Object::from_big_integer(left % right)
+        }
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "&" => Object::from_big_integer(left & right),
+        "|" => Object::from_big_integer(left | right),
+        "^" => Object::from_big_integer(left ^ right),
+        "<<" | ">>" if right < BigInt::ZERO => {
+            error(token, format!("negative shift amount: {}", right))
+        }
+        "<<" => match u32::try_from(&right) {
+            Ok(amount) if amount <= MAX_SHIFT => Object::from_big_integer(left << amount),
+            _ => error(token, format!("shift amount too large: {}", right)),
+        },
+        ">>" => match u32::try_from(&right) {
+            Ok(amount) => Object::from_big_integer(left >> amount),
+            Err(_) if left < BigInt::ZERO => Object::Integer(-1),
+            Err(_) => Object::Integer(0),
+        },
+        "<=" => Object::Boolean(left <= right),
+        ">=" => Object::Boolean(left >= right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => error(
+            token,
+            format!("unknown operator: INTEGER {} INTEGER", operator),
+        ),
+    }
+}
+
+fn eval_index_expression(token: &Token, left: Object, index: Object) -> Object {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(index)) => usize::try_from(index)
+            .ok()
+            .and_then(|index| elements.borrow().get(index).cloned())
+            .unwrap_or(Object::Null),
+        (Object::Array(_), Object::BigInteger(_)) => Object::Null,
+        (Object::Array(_), index) => error(
+            token,
+            format!("array index must be INTEGER, got {}", index.type_name()),
+        ),
+        (left, _) => error(
+            token,
+            format!("index operator not supported: {}", left.type_name()),
+        ),
+    }
+}
+
+// Lets, parameters and loop variables go in the slot the resolver picked, if it ran.
+fn declare(identifier: &Identifier, value: Object, env: &mut Environment) {
+    match identifier.binding {
+        Some(Binding::Local { slot, .. }) => env.set_local(slot, identifier.value, value),
+        _ => env.set(identifier.value, value),
+    }
+}
+
+fn error(token: &Token, message: String) -> Object {
+    Object::Error(format!("{}: {}", token.span.start, message))
+}
+
+// The address of a local, which is as deep as the native stack currently goes.
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+pub fn is_truthy(object: &Object) -> bool {
+    !matches!(object, Object::Null | Object::Boolean(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::limits::DEFAULT_STACK;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use std::time::Duration;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        program
+    }
+
+    // Errors found by resolving the program are returned as though it raised them.
+    fn eval_input(input: &str, integer_mode: IntegerMode) -> Object {
+        let mut program = parse(input);
+        let diagnostics = Resolver::with_integer_mode(integer_mode)
+            .resolve(&mut program)
+            .diagnostics;
+        if let Some(error) = diagnostics.iter().find(|diagnostic| diagnostic.is_error()) {
+            return Object::Error(error.to_string());
+        }
+
+        let env = Rc::new(RefCell::new(Environment::new()));
+        Evaluator::new(integer_mode).eval_program(&program, &env)
+    }
+
+    #[test]
+    fn test_eval_integer_expression() {
+        let tests = [
+            ("5", "5"),
+            ("-10", "-10"),
+            ("5 + 5 + 5 + 5 - 10", "10"),
+            ("2 * (5 + 10)", "30"),
+            ("50 / 2 * 2 + 10", "60"),
+            ("0xFF + 0b1010 + 0o10 + 1_000", "1273"),
+            ("3 * (3 * 3) + 10", "37"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression() {
+        let tests = [
+            ("1 < 2", "true"),
+            ("1 > 2", "false"),
+            ("(1 < 2) == true", "true"),
+            ("!5", "false"),
+            ("!!true", "true"),
+            ("true != false", "true"),
+            ("1 <= 1", "true"),
+            ("2 <= 1", "false"),
+            ("1 >= 2", "false"),
+            ("true && false", "false"),
+            ("true || false", "true"),
+            ("1 < 2 && 2 < 3", "true"),
+            ("false || 1 > 2", "false"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_statements_and_functions() {
+        let tests = [
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+            ("if (false) { 10 }", "null"),
+            ("if (false) { 1 } else if (true) { 2 } else { 3 }", "2"),
+            ("if (false) { 1 } else if (false) { 2 } else { 3 }", "3"),
+            ("if (false) { 1 } else if (false) { 2 }", "null"),
+            (
+                "let sign = fn(n) { if (n < 0) { -1 } else if (n == 0) { 0 } else { 1 } }; [sign(-5), sign(0), sign(5)]",
+                "[-1, 0, 1]",
+            ),
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", "10"),
+            ("let a = 5; let b = a * 2; b + a;", "15"),
+            ("let add = fn(x, y) { x + y; }; add(5, add(5, 5));", "15"),
+            (
+                "let adder = fn(x) { fn(y) { x + y } }; let add_two = adder(2); add_two(3);",
+                "5",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_remainder() {
+        let tests = [
+            ("7 % 3", "1"),
+            ("-7 % 3", "-1"),
+            ("7 % -3", "1"),
+            ("7 % 0", "ERROR: 1:3: division by zero"),
+            ("99999999999999999999 % 7", "1"),
+            ("let min = -9223372036854775807 - 1; min % -1", "0"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_bitwise_operators() {
+        let tests = [
+            ("12 & 10", "8"),
+            ("12 | 10", "14"),
+            ("12 ^ 10", "6"),
+            ("~0", "-1"),
+            ("~5", "-6"),
+            ("-1 & 255", "255"),
+            ("1 << 10", "1024"),
+            ("1024 >> 3", "128"),
+            ("-16 >> 2", "-4"),
+            ("-1 >> 100", "-1"),
+            ("1 >> 64", "0"),
+            ("1 << 64", "18446744073709551616"),
+            ("(1 << 64) >> 63", "2"),
+            ("~(1 << 64)", "-18446744073709551617"),
+            ("(1 << 64) | 1", "18446744073709551617"),
+            ("((1 << 64) + 255) & 15", "15"),
+            ("(1 << 64) ^ (1 << 64)", "0"),
+            ("1 << -1", "ERROR: 1:3: negative shift amount: -1"),
+            ("1 >> -1", "ERROR: 1:3: negative shift amount: -1"),
+            (
+                "1 << 4000000000",
+                "ERROR: 1:3: shift amount too large: 4000000000",
+            ),
+            (
+                "(1 << 64) << 16777217",
+                "ERROR: 1:11: shift amount too large: 16777217",
+            ),
+            (
+                "true & false",
+                "ERROR: 1:6: unknown operator: BOOLEAN & BOOLEAN",
+            ),
+            ("1 | true", "ERROR: 1:3: type mismatch: INTEGER | BOOLEAN"),
+            ("~true", "ERROR: 1:1: unknown operator: ~BOOLEAN"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_shift_overflow_modes() {
+        let tests = [
+            (IntegerMode::Checked, "1 << 62", "4611686018427387904"),
+            (
+                IntegerMode::Checked,
+                "1 << 63",
+                "ERROR: 1:3: integer overflow: 1 << 63",
+            ),
+            (IntegerMode::Checked, "-1 << 63", "-9223372036854775808"),
+            (
+                IntegerMode::Checked,
+                "3 << 64",
+                "ERROR: 1:3: integer overflow: 3 << 64",
+            ),
+            (IntegerMode::Checked, "0 << 100", "0"),
+            (IntegerMode::Wrapping, "1 << 63", "-9223372036854775808"),
+            (IntegerMode::Wrapping, "3 << 64", "0"),
+            (IntegerMode::Arbitrary, "1 << 63", "9223372036854775808"),
+        ];
+
+        for (integer_mode, input, expected) in tests {
+            assert_eq!(eval_input(input, integer_mode).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        let tests = [
+            ("false && 1 / 0", "false"),
+            ("true || 1 / 0", "true"),
+            ("true && 1 / 0", "ERROR: 1:11: division by zero"),
+            ("false || 1 / 0", "ERROR: 1:12: division by zero"),
+            ("5 && 0", "true"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_arrays() {
+        let tests = [
+            ("[1, 2 * 2, 3 + 3]", "[1, 4, 6]"),
+            ("[1, 2, 3][0]", "1"),
+            ("let i = 0; [1][i]", "1"),
+            ("let xs = [1, 2, 3]; xs[0] + xs[1] + xs[2]", "6"),
+            ("[1, 2, 3][3]", "null"),
+            ("[1, 2, 3][-1]", "null"),
+            (
+                "[1][true]",
+                "ERROR: 1:4: array index must be INTEGER, got BOOLEAN",
+            ),
+            ("1[0]", "ERROR: 1:2: index operator not supported: INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_assignment() {
+        let tests = [
+            ("let x = 1; x = 2; x", "2"),
+            ("let x = 1; x = 2", "2"),
+            ("let x = 1; let y = 1; x = y = 5; x + y", "10"),
+            ("let x = 10; x += 5; x -= 3; x *= 2; x /= 4; x", "6"),
+            (
+                "let counter = 0; let inc = fn() { counter += 1 }; inc(); inc(); counter",
+                "2",
+            ),
+            (
+                "let x = 1; let shadow = fn() { let x = 5; x = 6; x }; shadow() + x",
+                "7",
+            ),
+            ("let xs = [1, 2, 3]; xs[1] = 20; xs", "[1, 20, 3]"),
+            ("let xs = [1, 2, 3]; xs[2] *= 10; xs[2]", "30"),
+            ("let xs = [1]; let ys = xs; ys[0] = 9; xs", "[9]"),
+            ("let xs = [[0]]; xs[0][0] += 1; xs", "[[1]]"),
+            ("y = 1", "ERROR: 1:1: undefined identifier: y"),
+            ("y += 1", "ERROR: 1:1: undefined identifier: y"),
+            (
+                "let x = true; x += 1",
+                "ERROR: 1:17: type mismatch: BOOLEAN + INTEGER",
+            ),
+            (
+                "let xs = [1]; xs[1] = 2",
+                "ERROR: 1:17: index out of range: 1 (length 1)",
+            ),
+            (
+                "let xs = [1]; xs[-1] = 2",
+                "ERROR: 1:17: index out of range: -1 (length 1)",
+            ),
+            (
+                "let x = 1; x[0] = 2",
+                "ERROR: 1:13: index assignment not supported: INTEGER",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_loops() {
+        let tests = [
+            ("let i = 0; while (i < 10) { i += 1; } i", "10"),
+            ("let sum = 0; for (x in [1, 2, 3]) { sum += x; } sum", "6"),
+            (
+                "let i = 0; while (true) { i += 1; if (i == 5) { break; } } i",
+                "5",
+            ),
+            (
+                "let evens = 0; for (x in [1, 2, 3, 4]) { if (x % 2 == 1) { continue; } evens += x; } evens",
+                "6",
+            ),
+            (
+                "let n = 0; for (x in [1, 2, 3]) { for (y in [1, 2, 3]) { if (y > x) { break; } n += 1; } } n",
+                "6",
+            ),
+            (
+                "let n = 0; let i = 0; while (i < 3) { i += 1; let j = 0; while (true) { j += 1; if (j > i) { break; } if (j == 2) { continue; } n += 1; } } n",
+                "4",
+            ),
+            (
+                "let find = fn(xs) { for (x in xs) { if (x > 2) { return x; } } -1 }; find([1, 3, 5])",
+                "3",
+            ),
+            (
+                "let f = fn() { let i = 0; while (true) { i += 1; if (i == 3) { return i * 10; } } }; f()",
+                "30",
+            ),
+            (
+                "let n = 0; while (n < 3) { let f = fn() { for (x in [1]) { break; } 1 }; n += f(); } n",
+                "3",
+            ),
+            (
+                "let fs = []; let i = 0; for (x in [1, 2]) { let g = fn() { x }; if (i == 0) { fs = [g]; } else { fs = [fs[0], g]; } i += 1; } fs[0]() + fs[1]() * 10",
+                "21",
+            ),
+            ("for (x in []) { x } 1", "1"),
+            ("while (false) { 1 }", "null"),
+            ("let xs = [1, 2]; for (x in xs) { xs[1] = 5; } xs", "[1, 5]"),
+            ("for (x in [1]) { let y = x; } y", "ERROR: 1:31: undefined identifier: y"),
+            ("for (x in 5) { x }", "ERROR: 1:1: cannot iterate over INTEGER"),
+            ("while (1 / 0) { }", "ERROR: 1:10: division by zero"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_null_operators() {
+        let tests = [
+            ("null", "null"),
+            ("null == null", "true"),
+            ("null != null", "false"),
+            ("1 == null", "false"),
+            ("null != [1]", "true"),
+            ("!null", "true"),
+            ("null ?? 5", "5"),
+            ("3 ?? 5", "3"),
+            ("false ?? 5", "false"),
+            ("[1, 2][5] ?? 0", "0"),
+            ("1 ?? 1 / 0", "1"),
+            ("let xs = null; xs?.[0]", "null"),
+            ("let xs = [7]; xs?.[0]", "7"),
+            ("let xs = null; xs?.[1 / 0]", "null"),
+            ("let xs = [[1], null]; xs[1]?.[0] ?? -1", "-1"),
+            ("let f = null; f?.(1, 2)", "null"),
+            ("let f = fn(x) { x * 2 }; f?.(21)", "42"),
+            ("let f = null; f?.(1 / 0) ?? 0", "0"),
+            ("let a = null; a?.[0][1]", "null"),
+            ("let a = null; a?.[0](1)[2](3)", "null"),
+            ("let f = null; f?.(1)[0]", "null"),
+            (
+                "let a = [null]; a?.[0][1]",
+                "ERROR: 1:23: index operator not supported: NULL",
+            ),
+            (
+                "let xs = null; xs[0]",
+                "ERROR: 1:18: index operator not supported: NULL",
+            ),
+            ("let f = null; f(1)", "ERROR: 1:16: not a function: NULL"),
+            ("null + 1", "ERROR: 1:6: type mismatch: NULL + INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_unicode_source() {
+        let tests = [
+            ("let 合計 = 1 + 2; 合計 * 2", "6"),
+            ("let größe = 10; größe -= 3; größe", "7"),
+            ("\"こんにちは\" + \", \" + \"世界\"", "こんにちは, 世界"),
+            ("\"ä\" == \"ä\"", "true"),
+            ("\"a\" != \"b\"", "true"),
+            (
+                "let n = 0; for (c in \"日本語🎌\") { n += 1; } n // 4 characters",
+                "4",
+            ),
+            (
+                "let out = \"\"; for (c in \"åbc\") { out = c + out; } out",
+                "cbå",
+            ),
+            (
+                "\"a\" - \"b\"",
+                "ERROR: 1:5: unknown operator: STRING - STRING",
+            ),
+            ("\"a\" + 1", "ERROR: 1:5: type mismatch: STRING + INTEGER"),
+            ("未定義", "ERROR: 1:1: undefined identifier: 未定義"),
+            (
+                "let 値 = 1; 値 + true",
+                "ERROR: 1:14: type mismatch: INTEGER + BOOLEAN",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_checked_integer_errors() {
+        let tests = [
+            (
+                "9223372036854775807 + 1",
+                "ERROR: 1:21: integer overflow: 9223372036854775807 + 1",
+            ),
+            (
+                "-9223372036854775807 - 2",
+                "ERROR: 1:22: integer overflow: -9223372036854775807 - 2",
+            ),
+            (
+                "4611686018427387904 * 2",
+                "ERROR: 1:21: integer overflow: 4611686018427387904 * 2",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; min / -1",
+                "ERROR: 1:41: integer overflow: -9223372036854775808 / -1",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; -min",
+                "ERROR: 1:37: integer overflow: -(-9223372036854775808)",
+            ),
+            ("10 / 0", "ERROR: 1:4: division by zero"),
+            (
+                "let min = -9223372036854775807 - 1; min % -1",
+                "ERROR: 1:41: integer overflow: -9223372036854775808 % -1",
+            ),
+            (
+                "99999999999999999999",
+                "ERROR: 1:1: integer literal 99999999999999999999 is out of range for a 64-bit integer",
+            ),
+            ("5 + true", "ERROR: 1:3: type mismatch: INTEGER + BOOLEAN"),
+            ("foobar", "ERROR: 1:1: undefined identifier: foobar"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrapping_integer_arithmetic() {
+        let tests = [
+            ("9223372036854775807 + 1", "-9223372036854775808"),
+            ("-9223372036854775807 - 2", "9223372036854775807"),
+            ("4611686018427387904 * 2", "-9223372036854775808"),
+            (
+                "let min = -9223372036854775807 - 1; min / -1",
+                "-9223372036854775808",
+            ),
+            ("10 / 0", "ERROR: 1:4: division by zero"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Wrapping).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_precision_integers() {
+        let tests = [
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("-9223372036854775807 - 2", "-9223372036854775809"),
+            (
+                "99999999999999999999 * 99999999999999999999",
+                "9999999999999999999800000000000000000001",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; min / -1",
+                "9223372036854775808",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; -min",
+                "9223372036854775808",
+            ),
+            ("99999999999999999999 / 0", "ERROR: 1:22: division by zero"),
+            ("99999999999999999999 / 99999999999999999999", "1"),
+            (
+                "(9223372036854775807 + 1) - 1 == 9223372036854775807",
+                "true",
+            ),
+            ("99999999999999999999 > 1", "true"),
+            ("99999999999999999999 >= 99999999999999999999", "true"),
+            ("99999999999999999999 <= 1", "false"),
+            ("-99999999999999999999 < 99999999999999999999", "true"),
+            ("99999999999999999999 != 99999999999999999999", "false"),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(25)",
+                "15511210043330985984000000",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Arbitrary).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_functions_outlive_their_program() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let evaluator = Evaluator::default();
+        let mut resolver = Resolver::new();
+        let mut eval = |input: &str| {
+            let mut program = parse(input);
+            resolver.resolve(&mut program);
+            evaluator.eval_program(&program, &env)
+        };
+
+        eval("let add = fn(x) { fn(y) { x + y } };");
+        eval("let add_two = add(2);");
+
+        assert_eq!(eval("add_two").to_string(), "fn(y) { (x + y) }");
+        assert_eq!(eval("add_two(3)").to_string(), "5");
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let tests = [
+            (
+                "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } }; countdown(100000)",
+                "0",
+            ),
+            (
+                "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+                 [even(100000), odd(100001), even(7)]",
+                "[true, true, false]",
+            ),
+            (
+                "let sum = fn(n, total) { if (n == 0) { return total; } return sum(n - 1, total + n); }; sum(100000, 0)",
+                "5000050000",
+            ),
+            (
+                "let find = fn(n) { while (true) { if (n > 99999) { return n; } return find(n + 1); } }; find(0)",
+                "100000",
+            ),
+            (
+                "let sign = fn(n) { if (n < 0) { -1 } else if (n > 0) { sign(n - 1) } else { 0 } }; sign(100000)",
+                "0",
+            ),
+            ("let f = fn(n) { if (n == 0) { 0 } else { f(n - 1, 1) } }; f(3)", "ERROR: 1:43: wrong number of arguments: want=1, got=2"),
+            ("let f = fn() { g(); }; let g = fn() { 1 + true }; f()", "ERROR: 1:41: type mismatch: INTEGER + BOOLEAN"),
+            ("return gc();", "0"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let run = |limits: Limits, input: &str| {
+            let mut program = parse(input);
+            Resolver::new().resolve(&mut program);
+            let mut evaluator = Evaluator::new(IntegerMode::default());
+            evaluator.limits = limits;
+            let env = evaluator.new_environment();
+            evaluator.eval_program(&program, &env)
+        };
+        let steps = Limits {
+            steps: Some(1000),
+            ..Limits::default()
+        };
+        let call_depth = Limits {
+            call_depth: Some(100),
+            ..Limits::default()
+        };
+        let objects = Limits {
+            objects: Some(100),
+            ..Limits::default()
+        };
+        let time = Limits {
+            time: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        let integer_bits = Limits {
+            integer_bits: Some(1000),
+            ..Limits::default()
+        };
+        let size = Limits {
+            steps: Some(1000),
+            objects: Some(100),
+            size: 1 << 20,
+            ..Limits::default()
+        };
+        let recurse = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+        let tests = [
+            (
+                steps,
+                "while (true) { }",
+                "LIMIT EXCEEDED: took more than 1000 steps",
+            ),
+            (steps, "let a = [1, 2, 3]; a[0] + a[2]", "4"),
+            (
+                call_depth,
+                &format!("{} f(1000)", recurse),
+                "LIMIT EXCEEDED: calls nested more than 100 deep",
+            ),
+            (call_depth, &format!("{} f(99)", recurse), "99"),
+            (
+                call_depth,
+                "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(1000)",
+                "0",
+            ),
+            (
+                objects,
+                "let f = fn(n, a) { if (n == 0) { a } else { f(n - 1, [a]) } }; f(1000, [])",
+                "LIMIT EXCEEDED: more than 100 objects alive",
+            ),
+            (
+                objects,
+                "let i = 0; while (i < 1000) { let f = fn() { [f] }; i = i + 1; } i",
+                "1000",
+            ),
+            (
+                time,
+                "while (true) { }",
+                "LIMIT EXCEEDED: ran for more than 10ms",
+            ),
+            (
+                integer_bits,
+                "1 << 4000000000",
+                "LIMIT EXCEEDED: an integer over 1000 bits",
+            ),
+            (
+                integer_bits,
+                "let x = 3; while (true) { x = x * x; }",
+                "LIMIT EXCEEDED: an integer over 1000 bits",
+            ),
+            (
+                integer_bits,
+                "(1 << 900) * (1 << 200)",
+                "LIMIT EXCEEDED: an integer over 1000 bits",
+            ),
+            (integer_bits, "((1 << 900) + 1) >> 899", "2"),
+            (
+                size,
+                r#"let s = "x"; let i = 0; while (i < 40) { s = s + s; i += 1; }"#,
+                "LIMIT EXCEEDED: a string or array over 1048576 bytes",
+            ),
+            (
+                size,
+                r#"let s = "x"; let i = 0; while (i < 20) { s = s + s; i += 1; } i"#,
+                "20",
+            ),
+            (
+                steps,
+                "let f = fn() { while (true) { } }; let x = [f()]; 1",
+                "LIMIT EXCEEDED: took more than 1000 steps",
+            ),
+        ];
+
+        for (limits, input, expected) in tests {
+            assert_eq!(run(limits, input).to_string(), expected, "{}", input);
+        }
+        assert!(matches!(
+            run(steps, "while (true) { }"),
+            Object::LimitExceeded(Limit::Steps(1000))
+        ));
+    }
+
+    #[test]
+    fn test_display_nested_and_cyclic_arrays() {
+        let tests = [
+            ("let xs = [0]; xs[0] = xs; xs", "[[...]]"),
+            ("let a = [1]; let b = [a, a]; b", "[[1], [1]]"),
+            (
+                "let a = [1, [2]]; let b = [a, 3]; a[1][0] = b; b",
+                "[[1, [[...]]], 3]",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected
+            );
+        }
+
+        let nested = eval_input(
+            "let nest = fn(n, a) { if (n == 0) { a } else { nest(n - 1, [a]) } }; nest(1000, [])",
+            IntegerMode::Checked,
+        );
+        assert_eq!(nested.to_string().len(), 2 * 1001);
+    }
+
+    #[test]
+    fn test_return_unwinds_through_expressions() {
+        let tests = [
+            (
+                "let f = fn() { let x = if (true) { return 1; }; 2 }; f()",
+                "1",
+            ),
+            ("fn() { 1 + if (true) { return 1; } else { 0 } }()", "1"),
+            ("fn() { -if (true) { return 2; } }()", "2"),
+            ("fn() { [if (true) { return 3; }, 2] }()", "3"),
+            ("fn() { [1][if (true) { return 4; }] }()", "4"),
+            (
+                "let g = fn(x) { x }; fn() { g(if (true) { return 5; }) }()",
+                "5",
+            ),
+            ("fn() { let x = 0; x = if (true) { return 6; }; x }()", "6"),
+            (
+                "fn() { let a = [0]; a[0] += if (true) { return 7; }; a }()",
+                "7",
+            ),
+            ("fn() { true && if (true) { return 8; } }()", "8"),
+            ("fn() { for (x in if (true) { return 9; }) { } }()", "9"),
+            ("fn() { return if (true) { return 10; }; }()", "10"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_break_and_continue_unwind_through_expressions() {
+        let tests = [
+            (
+                "let i = 0; while (i < 3) { i = i + 1; let x = if (true) { break; }; } i",
+                "1",
+            ),
+            (
+                "let i = 0; let x = 0; while (i < 3) { i = i + 1; x = if (true) { continue; }; } [i, x]",
+                "[3, 0]",
+            ),
+            (
+                "let n = 0; for (i in [1, 2, 3]) { n = n + if (i == 2) { break; } else { i }; } n",
+                "1",
+            ),
+            (
+                "let n = 0; for (i in [1, 2, 3]) { n = n + if (i == 2) { continue; } else { i }; } n",
+                "4",
+            ),
+            (
+                "let a = []; for (i in [1, 2]) { a = [a, [if (i == 2) { break; }]]; } a",
+                "[[], [null]]",
+            ),
+            (
+                "let a = [0]; for (i in [1, 2]) { a[if (i == 2) { continue; } else { 0 }] = i; } a",
+                "[1]",
+            ),
+            (
+                "let n = 0; let f = fn(x) { x }; for (i in [1, 2]) { n = f(if (i == 2) { break; } else { i }); } n",
+                "1",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                eval_input(input, IntegerMode::Checked).to_string(),
+                expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_deep_recursion_exceeds_the_stack_limit() {
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)";
+        let mut program = parse(input);
+        Resolver::new().resolve(&mut program);
+        for call_depth in [None, Some(1_000_000)] {
+            let mut evaluator = Evaluator::new(IntegerMode::default());
+            evaluator.limits.call_depth = call_depth;
+            let env = evaluator.new_environment();
+            assert!(matches!(
+                evaluator.eval_program(&program, &env),
+                Object::LimitExceeded(Limit::Stack(DEFAULT_STACK))
+            ));
+        }
+        assert_eq!(
+            eval_input(
+                "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100)",
+                IntegerMode::Checked
+            )
+            .to_string(),
+            "100"
+        );
+    }
+}
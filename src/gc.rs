@@ -0,0 +1,314 @@
+use crate::environment::Environment;
+use crate::object::{Array, Object};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+// No collection runs before this many objects are tracked. After that, one runs whenever
+// the number tracked has doubled since the last.
+const FIRST_COLLECTION: usize = 1024;
+
+/// Tracks the environments and arrays a program allocates, so that reference cycles among
+/// them can be collected.
+///
+/// Values are reference counted, which frees anything unreachable at once unless it is part
+/// of a cycle, like a closure stored in the environment it captured. A collection finds
+/// those by mark and sweep. References from outside the heap, such as the evaluator's own
+/// locals, can't be seen directly, so the roots are the objects with more references than
+/// the heap accounts for. Everything unreachable from a root is swept by clearing its
+/// bindings or elements, which breaks its cycles and lets reference counting free it.
+#[derive(Debug, Default)]
+pub struct Heap {
+    environments: Vec<Weak<RefCell<Environment>>>,
+    arrays: Vec<Weak<RefCell<Array>>>,
+    threshold: usize,
+    allocated: u64,
+    collections: u64,
+    freed: u64,
+}
+
+/// A snapshot of the heap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Environments still alive.
+    pub environments: usize,
+    /// Arrays still alive.
+    pub arrays: usize,
+    /// Environments and arrays allocated so far.
+    pub allocated: u64,
+    pub collections: u64,
+    /// Objects that collections found unreachable and freed.
+    pub freed: u64,
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "heap: {} environments and {} arrays alive, {} allocated, {} freed by {} collections",
+            self.environments, self.arrays, self.allocated, self.freed, self.collections
+        )
+    }
+}
+
+impl Heap {
+    pub fn environment(&mut self, env: Environment) -> Rc<RefCell<Environment>> {
+        self.before_allocation();
+        let env = Rc::new(RefCell::new(env));
+        self.environments.push(Rc::downgrade(&env));
+        env
+    }
+
+    pub fn array(&mut self, elements: Vec<Object>) -> Rc<RefCell<Array>> {
+        self.before_allocation();
+        let array = Rc::new(RefCell::new(Array::from(elements)));
+        self.arrays.push(Rc::downgrade(&array));
+        array
+    }
+
+    fn before_allocation(&mut self) {
+        self.allocated += 1;
+        if self.tracked() >= self.threshold.max(FIRST_COLLECTION) {
+            self.collect();
+            self.threshold = 2 * self.tracked();
+        }
+    }
+
+    /// Objects being tracked, which includes any freed since the last collection.
+    pub fn tracked(&self) -> usize {
+        self.environments.len() + self.arrays.len()
+    }
+
+    /// Frees every object that is only reachable through cycles and returns how many there
+    /// were.
+    pub fn collect(&mut self) -> usize {
+        self.collections += 1;
+        let environments: Vec<_> = self.environments.iter().filter_map(Weak::upgrade).collect();
+        let arrays: Vec<_> = self.arrays.iter().filter_map(Weak::upgrade).collect();
+
+        let mut nodes: HashMap<usize, usize> = HashMap::new();
+        for (node, env) in environments.iter().enumerate() {
+            nodes.insert(address(env), node);
+        }
+        for (node, array) in arrays.iter().enumerate() {
+            nodes.insert(address(array), environments.len() + node);
+        }
+
+        // An object that is borrowed can't be looked into. Leaving out its references can
+        // only make more roots, so that is safe.
+        let count = nodes.len();
+        let mut edges = vec![Vec::new(); count];
+        let mut roots = vec![false; count];
+        for (node, env) in environments.iter().enumerate() {
+            match env.try_borrow() {
+                Ok(env) => {
+                    if let Some(outer) = env.outer() {
+                        edges[node].push(address(outer));
+                    }
+                    for value in env.values() {
+                        references(value, &mut edges[node]);
+                    }
+                }
+                Err(_) => roots[node] = true,
+            }
+        }
+        for (node, array) in arrays.iter().enumerate() {
+            let node = environments.len() + node;
+            match array.try_borrow() {
+                Ok(elements) => {
+                    for value in elements.iter() {
+                        references(value, &mut edges[node]);
+                    }
+                }
+                Err(_) => roots[node] = true,
+            }
+        }
+        let edges: Vec<Vec<usize>> = edges
+            .into_iter()
+            .map(|edges| {
+                edges
+                    .iter()
+                    .filter_map(|edge| nodes.get(edge).copied())
+                    .collect()
+            })
+            .collect();
+
+        let mut internal = vec![0; count];
+        for &edge in edges.iter().flatten() {
+            internal[edge] += 1;
+        }
+        // Less the reference upgrading the weak one made.
+        let strong = environments
+            .iter()
+            .map(|env| Rc::strong_count(env) - 1)
+            .chain(arrays.iter().map(|array| Rc::strong_count(array) - 1));
+        for (node, strong) in strong.enumerate() {
+            roots[node] |= strong > internal[node];
+        }
+
+        let mut marked = roots.clone();
+        let mut pending: Vec<usize> = (0..count).filter(|&node| roots[node]).collect();
+        while let Some(node) = pending.pop() {
+            for &edge in &edges[node] {
+                if !mem::replace(&mut marked[edge], true) {
+                    pending.push(edge);
+                }
+            }
+        }
+
+        // The contents are dropped once the borrow has ended, since dropping them can free
+        // other objects.
+        let mut freed = 0;
+        for (node, env) in environments.iter().enumerate() {
+            if !marked[node] {
+                drop(mem::take(&mut *env.borrow_mut()));
+                freed += 1;
+            }
+        }
+        for (node, array) in arrays.iter().enumerate() {
+            if !marked[environments.len() + node] {
+                drop(mem::take(&mut *array.borrow_mut()));
+                freed += 1;
+            }
+        }
+        drop((environments, arrays));
+
+        self.environments.retain(|env| env.strong_count() > 0);
+        self.arrays.retain(|array| array.strong_count() > 0);
+        self.freed += freed as u64;
+        freed
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            environments: self
+                .environments
+                .iter()
+                .filter(|env| env.strong_count() > 0)
+                .count(),
+            arrays: self
+                .arrays
+                .iter()
+                .filter(|array| array.strong_count() > 0)
+                .count(),
+            allocated: self.allocated,
+            collections: self.collections,
+            freed: self.freed,
+        }
+    }
+}
+
+fn address<T>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as usize
+}
+
+// The environments and arrays `value` refers to directly.
+fn references(value: &Object, out: &mut Vec<usize>) {
+    match value {
+        Object::Function(function) => out.push(address(&function.env)),
+        Object::Array(elements) => out.push(address(elements)),
+        Object::ReturnValue(value) => references(value, out),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use std::rc::Rc;
+
+    fn eval(evaluator: &Evaluator, input: &str) -> String {
+        let mut program = Parser::new(Lexer::new(input)).parse();
+        Resolver::new().resolve(&mut program);
+        let env = evaluator.new_environment();
+        evaluator.eval_program(&program, &env).to_string()
+    }
+
+    #[test]
+    fn test_gc_frees_unreachable_cycles() {
+        let input = "
+let make = fn() {
+  let count = fn(n) { if (n == 0) { 0 } else { count(n - 1) } };
+  count
+};
+let kept = make();
+let f = fn() {
+  let g = fn(n) { if (n > 0) { g(n - 1) } else { 0 } };
+  g(3)
+};
+f();
+let loop = fn() { let a = [1]; a[0] = a; 0 };
+loop();
+let freed = gc();
+[freed, kept(5), gc()]";
+
+        let evaluator = Evaluator::default();
+        // `f`'s scope, which holds `g`, and the array that holds itself.
+        assert_eq!(eval(&evaluator, input), "[2, 0, 0]");
+        assert_eq!(evaluator.heap_stats().freed, 2);
+    }
+
+    #[test]
+    fn test_gc_frees_a_dropped_global_scope() {
+        let evaluator = Evaluator::default();
+        let mut program = Parser::new(Lexer::new(
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10)",
+        ))
+        .parse();
+        Resolver::new().resolve(&mut program);
+
+        let env = evaluator.new_environment();
+        assert_eq!(evaluator.eval_program(&program, &env).to_string(), "55");
+        let weak = Rc::downgrade(&env);
+        drop(env);
+        assert!(
+            weak.upgrade().is_some(),
+            "the scope holds `fib`, which holds the scope"
+        );
+
+        assert_eq!(evaluator.collect_garbage(), 1);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(evaluator.heap_stats().environments, 0);
+    }
+
+    #[test]
+    fn test_collections_run_as_the_heap_grows() {
+        let evaluator = Evaluator::default();
+        let input = "
+let i = 0;
+while (i < 5000) {
+  let f = fn() { f };
+  i = i + 1;
+}
+i";
+        assert_eq!(eval(&evaluator, input), "5000");
+
+        let stats = evaluator.heap_stats();
+        assert!(stats.collections > 0, "{}", stats);
+        assert!(
+            stats.environments < 2 * super::FIRST_COLLECTION,
+            "{}",
+            stats
+        );
+
+        // Each iteration's scope holds the `f` that captured it, and they all hold the
+        // global scope.
+        evaluator.collect_garbage();
+        let stats = evaluator.heap_stats();
+        assert_eq!(stats.environments, 0, "{}", stats);
+        assert_eq!(stats.freed, 5001, "{}", stats);
+    }
+
+    #[test]
+    fn test_gc_arguments() {
+        assert_eq!(
+            eval(&Evaluator::default(), "gc(1)"),
+            "ERROR: 1:3: wrong number of arguments: want=0, got=1"
+        );
+    }
+}
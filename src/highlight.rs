@@ -0,0 +1,250 @@
+use crate::lexer::{tokenize, Cursor};
+use crate::token::TokenKind;
+
+/// What a piece of highlighted source is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Keyword,
+    Identifier,
+    /// Integers, strings, `true`, `false` and `null`.
+    Literal,
+    Operator,
+    Punctuation,
+    Comment,
+    /// Characters the lexer couldn't make a token of, such as an unterminated string.
+    Error,
+}
+
+impl Class {
+    pub fn of(kind: &TokenKind) -> Class {
+        match kind {
+            TokenKind::FUNCTION
+            | TokenKind::LET
+            | TokenKind::IF
+            | TokenKind::ELSE
+            | TokenKind::RETURN
+            | TokenKind::WHILE
+            | TokenKind::FOR
+            | TokenKind::IN
+            | TokenKind::BREAK
+            | TokenKind::CONTINUE => Class::Keyword,
+            TokenKind::IDENTIFIER => Class::Identifier,
+            TokenKind::INTEGER
+            | TokenKind::STRING
+            | TokenKind::TRUE
+            | TokenKind::FALSE
+            | TokenKind::NULL => Class::Literal,
+            TokenKind::COMMA
+            | TokenKind::SEMICOLON
+            | TokenKind::LPAREN
+            | TokenKind::RPAREN
+            | TokenKind::LBRACE
+            | TokenKind::RBRACE
+            | TokenKind::LBRACKET
+            | TokenKind::RBRACKET => Class::Punctuation,
+            TokenKind::ILLEGAL | TokenKind::EOF => Class::Error,
+            _ => Class::Operator,
+        }
+    }
+
+    /// The CSS class `to_html` gives the class's spans.
+    pub fn css_class(self) -> &'static str {
+        match self {
+            Class::Keyword => "keyword",
+            Class::Identifier => "identifier",
+            Class::Literal => "literal",
+            Class::Operator => "operator",
+            Class::Punctuation => "punctuation",
+            Class::Comment => "comment",
+            Class::Error => "error",
+        }
+    }
+
+    // SGR parameters; identifiers and punctuation are left in the terminal's own color.
+    fn ansi_style(self) -> Option<&'static str> {
+        match self {
+            Class::Keyword => Some("1;35"),
+            Class::Literal => Some("32"),
+            Class::Operator => Some("33"),
+            Class::Comment => Some("90"),
+            Class::Error => Some("4;31"),
+            Class::Identifier | Class::Punctuation => None,
+        }
+    }
+}
+
+/// Splits `source` into pieces that together spell it out exactly, each with its class.
+/// Whitespace has no class.
+pub fn segments(source: &str) -> Vec<(Option<Class>, &str)> {
+    let mut segments = Vec::new();
+    let mut cursor = Cursor::new(source);
+
+    for token in tokenize(source) {
+        let from = cursor.offset();
+        let start = cursor.advance_to(token.span.start);
+        push_gap(&mut segments, &source[from..start]);
+        let end = cursor.advance_to(token.span.end);
+        segments.push((Some(Class::of(&token.kind)), &source[start..end]));
+    }
+    push_gap(&mut segments, &source[cursor.offset()..]);
+    segments
+}
+
+// The lexer skips whitespace and comments, so anything between two tokens is one or the other.
+fn push_gap<'a>(segments: &mut Vec<(Option<Class>, &'a str)>, mut gap: &'a str) {
+    while let Some(comment) = gap.find("//") {
+        let end = gap[comment..]
+            .find('\n')
+            .map_or(gap.len(), |end| comment + end);
+        if comment > 0 {
+            segments.push((None, &gap[..comment]));
+        }
+        segments.push((Some(Class::Comment), &gap[comment..end]));
+        gap = &gap[end..];
+    }
+    if !gap.is_empty() {
+        segments.push((None, gap));
+    }
+}
+
+/// Highlights `source` with ANSI escape codes for a terminal.
+pub fn to_ansi(source: &str) -> String {
+    let mut out = String::new();
+    for (class, text) in segments(source) {
+        match class.and_then(Class::ansi_style) {
+            Some(style) => out += &format!("\x1b[{}m{}\x1b[0m", style, text),
+            None => out += text,
+        }
+    }
+    out
+}
+
+/// Highlights `source` as an HTML `<pre>` block. Each token is a `<span>` whose CSS class
+/// is named by `Class::css_class`.
+pub fn to_html(source: &str) -> String {
+    let mut out = String::from("<pre class=\"monkey\"><code>");
+    for (class, text) in segments(source) {
+        match class {
+            Some(class) => {
+                out += &format!(
+                    "<span class=\"{}\">{}</span>",
+                    class.css_class(),
+                    escape_html(text)
+                )
+            }
+            None => out += text,
+        }
+    }
+    out += "</code></pre>";
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments() {
+        let input = "let s = \"a // b\"; // note\nif (x <= 0x1F) { s?.len } @";
+
+        let expected = vec![
+            (Some(Class::Keyword), "let"),
+            (None, " "),
+            (Some(Class::Identifier), "s"),
+            (None, " "),
+            (Some(Class::Operator), "="),
+            (None, " "),
+            (Some(Class::Literal), "\"a // b\""),
+            (Some(Class::Punctuation), ";"),
+            (None, " "),
+            (Some(Class::Comment), "// note"),
+            (None, "\n"),
+            (Some(Class::Keyword), "if"),
+            (None, " "),
+            (Some(Class::Punctuation), "("),
+            (Some(Class::Identifier), "x"),
+            (None, " "),
+            (Some(Class::Operator), "<="),
+            (None, " "),
+            (Some(Class::Literal), "0x1F"),
+            (Some(Class::Punctuation), ")"),
+            (None, " "),
+            (Some(Class::Punctuation), "{"),
+            (None, " "),
+            (Some(Class::Identifier), "s"),
+            (Some(Class::Operator), "?."),
+            (Some(Class::Identifier), "len"),
+            (None, " "),
+            (Some(Class::Punctuation), "}"),
+            (None, " "),
+            (Some(Class::Error), "@"),
+        ];
+        assert_eq!(segments(input), expected);
+    }
+
+    #[test]
+    fn test_segments_spell_out_the_source() {
+        let inputs = vec![
+            "",
+            "   \n\t",
+            "// only a comment",
+            "let a = 1; // one\r\n// two\r\nlet b = 2;\r\n",
+            "let café = \"naïve 😀\"; café",
+            "let s = \"unterminated",
+            "true && !false || null ?? 1_000",
+        ];
+
+        for input in inputs {
+            let joined: String = segments(input).iter().map(|(_, text)| *text).collect();
+            assert_eq!(joined, input);
+        }
+
+        let segments = segments("let s = \"unterminated");
+        assert_eq!(
+            segments.last(),
+            Some(&(Some(Class::Error), "\"unterminated"))
+        );
+    }
+
+    #[test]
+    fn test_to_ansi() {
+        assert_eq!(
+            to_ansi("let x = 1; // one"),
+            "\x1b[1;35mlet\x1b[0m x \x1b[33m=\x1b[0m \x1b[32m1\x1b[0m; \x1b[90m// one\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_to_html() {
+        assert_eq!(
+            to_html("a < \"<b>\""),
+            "<pre class=\"monkey\"><code>\
+             <span class=\"identifier\">a</span> \
+             <span class=\"operator\">&lt;</span> \
+             <span class=\"literal\">&quot;&lt;b&gt;&quot;</span>\
+             </code></pre>"
+        );
+        assert_eq!(
+            to_html("x & y\n"),
+            "<pre class=\"monkey\"><code>\
+             <span class=\"identifier\">x</span> \
+             <span class=\"operator\">&amp;</span> \
+             <span class=\"identifier\">y</span>\n\
+             </code></pre>"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use crate::ast::Program;
+use crate::environment::Environment;
+use crate::evaluator::{Evaluator, Hook, IntegerMode};
+use crate::gc::HeapStats;
+use crate::lexer::Lexer;
+use crate::limits::{Limit, Limits};
+use crate::object::Object;
+use crate::optimizer;
+use crate::parser::{ParseError, Parser};
+use crate::resolver::{Diagnostic, Resolver};
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::mem;
+use std::rc::Rc;
+
+/// Runs Monkey code on behalf of a host program. Globals persist from one call to the
+/// next, as they do in the REPL, and the host can read and define them too.
+#[derive(Debug)]
+pub struct Interpreter {
+    evaluator: Evaluator,
+    resolver: Resolver,
+    env: Rc<RefCell<Environment>>,
+}
+
+/// Why code run by an [`Interpreter`] failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The source didn't parse.
+    Parse(Vec<ParseError>),
+    /// The source parsed but can't run, such as for referring to an undefined name.
+    Resolve(Vec<Diagnostic>),
+    /// The code raised an error while running.
+    Runtime(String),
+    /// The code ran out of one of the interpreter's limits.
+    LimitExceeded(Limit),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => write_lines(f, errors),
+            Error::Resolve(diagnostics) => write_lines(f, diagnostics),
+            Error::Runtime(message) => write!(f, "{}", message),
+            Error::LimitExceeded(limit) => write!(f, "limit exceeded: {}", limit),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn write_lines<T: Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new(IntegerMode::default())
+    }
+}
+
+impl Interpreter {
+    pub fn new(integer_mode: IntegerMode) -> Self {
+        let evaluator = Evaluator::new(integer_mode);
+        let env = evaluator.new_environment();
+        Interpreter {
+            evaluator,
+            resolver: Resolver::with_integer_mode(integer_mode),
+            env,
+        }
+    }
+
+    /// Bounds what each later call to `eval_str` or `call_function` may use.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.evaluator.limits = limits;
+    }
+
+    /// Runs `source` and returns the value of its last statement.
+    pub fn eval_str(&mut self, source: &str) -> Result<Object, Error> {
+        let (program, _) = self.compile(source)?;
+        into_result(self.run(&program))
+    }
+
+    /// Parses and resolves `source` against the globals so far, ready to run, along with the
+    /// warnings resolving it raised.
+    pub(crate) fn compile(&mut self, source: &str) -> Result<(Program, Vec<Diagnostic>), Error> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let mut program = parser.parse();
+        if !parser.errors.is_empty() {
+            return Err(Error::Parse(parser.errors));
+        }
+
+        let (errors, warnings) = self
+            .resolver
+            .resolve(&mut program)
+            .diagnostics
+            .into_iter()
+            .partition::<Vec<_>, _>(Diagnostic::is_error);
+        if !errors.is_empty() {
+            return Err(Error::Resolve(errors));
+        }
+        Ok((program, warnings))
+    }
+
+    pub(crate) fn optimize(&self, program: &mut Program) {
+        optimizer::optimize(program, &self.evaluator);
+    }
+
+    /// Runs a compiled program in the global scope.
+    pub(crate) fn run(&self, program: &Program) -> Object {
+        self.evaluator.eval_program(program, &self.env)
+    }
+
+    /// Runs a compiled program with `hook` watching, and hands the hook back afterwards.
+    pub(crate) fn run_hooked<H: Hook + 'static>(
+        &mut self,
+        program: &Program,
+        hook: H,
+    ) -> (Object, H) {
+        let hook = Rc::new(hook);
+        self.evaluator.hook = Some(hook.clone());
+        let result = self.run(program);
+        self.evaluator.hook = None;
+        let Ok(hook) = Rc::try_unwrap(hook) else {
+            unreachable!("functions don't keep the evaluator's hook");
+        };
+        (result, hook)
+    }
+
+    pub(crate) fn heap_stats(&self) -> HeapStats {
+        self.evaluator.heap_stats()
+    }
+
+    /// Binds `name` in the global scope, as a `let` at the top level would.
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        let name = Symbol::intern(name);
+        self.resolver.declare_global(name);
+        self.env.borrow_mut().set(name, value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        // A name that was never interned can't be bound, and interning it would keep it
+        // around for nothing.
+        self.env.borrow().get_global(Symbol::lookup(name)?)
+    }
+
+    /// Calls the global function `name` with `arguments`.
+    pub fn call_function(&mut self, name: &str, arguments: Vec<Object>) -> Result<Object, Error> {
+        let Some(function) = self.get_global(name) else {
+            return Err(Error::Runtime(format!("identifier not found: {}", name)));
+        };
+        into_result(self.evaluator.call_function(function, arguments))
+    }
+}
+
+// Functions defined by the code hold the global scope, so it is only freed by collecting it
+// along with them. Values the host still holds keep what they need alive.
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        drop(mem::replace(
+            &mut self.env,
+            Rc::new(RefCell::new(Environment::new())),
+        ));
+        self.evaluator.collect_garbage();
+    }
+}
+
+fn into_result(object: Object) -> Result<Object, Error> {
+    match object {
+        Object::Error(message) => Err(Error::Runtime(message)),
+        Object::LimitExceeded(limit) => Err(Error::LimitExceeded(limit)),
+        object => Ok(object),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_str_keeps_globals() {
+        let mut interpreter = Interpreter::default();
+        interpreter.eval_str("let x = 5;").unwrap();
+        let result = interpreter.eval_str("let y = x * 2; y + 1").unwrap();
+
+        assert_eq!(result.to_string(), "11");
+        assert_eq!(interpreter.get_global("y").unwrap().to_string(), "10");
+        assert!(interpreter.get_global("z").is_none());
+        assert!(interpreter.get_global("never_defined_anywhere").is_none());
+        assert!(Symbol::lookup("never_defined_anywhere").is_none());
+    }
+
+    #[test]
+    fn test_set_global_and_call_function() {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_global("base", Object::Integer(100));
+        interpreter
+            .eval_str("let add = fn(x, y) { base + x + y };")
+            .unwrap();
+
+        let result = interpreter
+            .call_function("add", vec![Object::Integer(1), Object::Integer(2)])
+            .unwrap();
+        assert_eq!(result.to_string(), "103");
+
+        let error = interpreter
+            .call_function("add", vec![Object::Integer(1)])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "wrong number of arguments: want=2, got=1"
+        );
+        let error = interpreter.call_function("base", Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "not a function: INTEGER");
+        let error = interpreter
+            .call_function("missing", Vec::new())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "identifier not found: missing");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut interpreter = Interpreter::default();
+        assert!(matches!(
+            interpreter.eval_str("let = 1;"),
+            Err(Error::Parse(_))
+        ));
+
+        let error = interpreter.eval_str("undefined + 1").unwrap_err();
+        assert!(matches!(error, Error::Resolve(_)));
+        assert_eq!(error.to_string(), "1:1: undefined identifier: undefined");
+
+        let error = interpreter.eval_str("1 + true").unwrap_err();
+        assert_eq!(error.to_string(), "1:3: type mismatch: INTEGER + BOOLEAN");
+
+        interpreter.set_limits(Limits {
+            steps: Some(100),
+            ..Limits::default()
+        });
+        let error = interpreter.eval_str("while (true) { }").unwrap_err();
+        assert!(matches!(error, Error::LimitExceeded(Limit::Steps(100))));
+        assert_eq!(interpreter.eval_str("1 + 1").unwrap().to_string(), "2");
+    }
+
+    #[test]
+    fn test_functions_returned_to_the_host_outlive_it() {
+        let mut interpreter = Interpreter::default();
+        let counter = interpreter
+            .eval_str("let n = 41; let next = fn() { n + 1 }; next")
+            .unwrap();
+        drop(interpreter);
+
+        let evaluator = Evaluator::default();
+        assert_eq!(
+            evaluator.call_function(counter, Vec::new()).to_string(),
+            "42"
+        );
+    }
+
+    #[test]
+    fn test_dropping_deeply_nested_values() {
+        let mut interpreter = Interpreter::default();
+        let arrays = "
+let a = [];
+let i = 0;
+while (i < 100000) { a = [a]; i += 1; }
+a = 0;
+i";
+        assert_eq!(interpreter.eval_str(arrays).unwrap().to_string(), "100000");
+
+        // Each function captures the scope holding the one before it.
+        let closures = "
+let f = fn() { 0 };
+let i = 0;
+while (i < 100000) { let g = f; f = fn() { g() }; i += 1; }
+f = 0;
+i";
+        assert_eq!(
+            interpreter.eval_str(closures).unwrap().to_string(),
+            "100000"
+        );
+    }
+}
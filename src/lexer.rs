@@ -1,106 +1,270 @@
-use crate::token::{Token, TokenKind};
+use crate::symbol::{kw, Symbol};
+use crate::token::{Position, Span, Token, TokenKind};
 use std::iter::Peekable;
 use std::str::Chars;
+use unicode_ident::{is_xid_continue, is_xid_start};
 
-pub fn tokenize(input: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let mut input = input.chars().peekable();
+/// Lexes source text lazily, producing one token per call to `next`.
+/// Token literals borrow from the input, so lexing doesn't allocate.
+/// Columns count characters, not bytes, so multi-byte UTF-8 text doesn't skew them.
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<Chars<'a>>,
+    // Byte offset of the next character, used to slice literals out of `input`.
+    offset: usize,
+    position: Position,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer::resume(input, 0, Position::default())
+    }
 
-    while let Some(c) = input.next() {
-        if c.is_whitespace() {
-            continue;
+    /// Lexes `input` from byte `offset`, which must be at `position` and not inside a token.
+    pub fn resume(input: &'a str, offset: usize, position: Position) -> Self {
+        Lexer {
+            input,
+            chars: input[offset..].chars().peekable(),
+            offset,
+            position,
         }
-        match c {
-            '=' => {
-                if let Some(c) = input.peek() {
-                    if *c == '=' {
-                        tokens.push(Token::new(TokenKind::EQ, String::from("==")));
-                        input.next();
-                    } else {
-                        tokens.push(Token::new(TokenKind::ASSIGN, String::from("=")));
-                    }
-                }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        advance(&mut self.position, c);
+        Some(c)
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    // Consumes the next character if it is `expected`.
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(&expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn slice(&self, from: usize) -> &'a str {
+        &self.input[from..self.offset]
+    }
+
+    // Takes the whole alphanumeric run, so radix prefixes, `_` separators and any
+    // invalid digits stay in the literal for the parser to check.
+    fn consume_integer(&mut self, from: usize) -> &'a str {
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            self.bump();
+        }
+        self.slice(from)
+    }
+
+    fn consume_literal(&mut self, from: usize) -> &'a str {
+        while self.peek().is_some_and(|c| is_identifier_continue(*c)) {
+            self.bump();
+        }
+        self.slice(from)
+    }
+
+    // Line comments run from `//` to the end of the line.
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
             }
-            '+' => tokens.push(Token::new(TokenKind::PLUS, String::from("+"))),
-            '-' => tokens.push(Token::new(TokenKind::MINUS, String::from("-"))),
-            '!' => {
-                if let Some(c) = input.peek() {
-                    if *c == '=' {
-                        tokens.push(Token::new(TokenKind::NOT_EQ, String::from("!=")));
-                        input.next();
-                    } else {
-                        tokens.push(Token::new(TokenKind::BANG, String::from("!")));
-                    }
+        }
+    }
+
+    // The literal keeps the source spelling between the quotes; escapes are decoded by the parser.
+    // An unterminated string becomes an ILLEGAL token.
+    fn consume_string(&mut self, from: usize) -> Token<'a> {
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => {
+                    let literal = &self.input[from + 1..self.offset - 1];
+                    return Token::new(TokenKind::STRING, literal);
                 }
-            }
-            '*' => tokens.push(Token::new(TokenKind::ASTERISK, String::from("*"))),
-            '/' => tokens.push(Token::new(TokenKind::SLASH, String::from("/"))),
-            '<' => tokens.push(Token::new(TokenKind::LT, String::from("<"))),
-            '>' => tokens.push(Token::new(TokenKind::GT, String::from(">"))),
-            ',' => tokens.push(Token::new(TokenKind::COMMA, String::from(","))),
-            ';' => tokens.push(Token::new(TokenKind::SEMICOLON, String::from(";"))),
-            '(' => tokens.push(Token::new(TokenKind::LPAREN, String::from("("))),
-            ')' => tokens.push(Token::new(TokenKind::RPAREN, String::from(")"))),
-            '{' => tokens.push(Token::new(TokenKind::LBRACE, String::from("{"))),
-            '}' => tokens.push(Token::new(TokenKind::RBRACE, String::from("}"))),
-            _ => {
-                if c.is_ascii_digit() {
-                    tokens.push(Token::new(
-                        TokenKind::INTEGER,
-                        consume_integer(&mut input, c),
-                    ));
-                } else if is_literal(c) {
-                    let literal = consume_literal(&mut input, c);
-                    tokens.push(search_keywords(&literal));
-                } else {
-                    tokens.push(Token::new(TokenKind::ILLEGAL, String::from(c)));
+                '\\' => {
+                    self.bump();
                 }
+                _ => {}
             }
         }
+        Token::new(TokenKind::ILLEGAL, self.slice(from))
     }
-    tokens
 }
 
-fn consume_integer(input: &mut Peekable<Chars>, current_c: char) -> String {
-    let mut num = String::from(current_c);
-    while let Some(c) = input.peek() {
-        if c.is_ascii_digit() {
-            num.push(*c);
-            input.next();
-        } else {
-            break;
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let start = self.position;
+            let from = self.offset;
+            let c = self.bump()?;
+            if c.is_whitespace() {
+                continue;
+            }
+            let token = match c {
+                '=' if self.eat('=') => Token::new(TokenKind::EQ, "=="),
+                '=' => Token::new(TokenKind::ASSIGN, "="),
+                '+' if self.eat('=') => Token::new(TokenKind::PLUS_ASSIGN, "+="),
+                '+' => Token::new(TokenKind::PLUS, "+"),
+                '-' if self.eat('=') => Token::new(TokenKind::MINUS_ASSIGN, "-="),
+                '-' => Token::new(TokenKind::MINUS, "-"),
+                '!' if self.eat('=') => Token::new(TokenKind::NOT_EQ, "!="),
+                '!' => Token::new(TokenKind::BANG, "!"),
+                '*' if self.eat('=') => Token::new(TokenKind::ASTERISK_ASSIGN, "*="),
+                '*' => Token::new(TokenKind::ASTERISK, "*"),
+                '/' if self.eat('=') => Token::new(TokenKind::SLASH_ASSIGN, "/="),
+                '/' if self.eat('/') => {
+                    self.skip_comment();
+                    continue;
+                }
+                '/' => Token::new(TokenKind::SLASH, "/"),
+                '"' => self.consume_string(from),
+                '%' => Token::new(TokenKind::PERCENT, "%"),
+                '<' if self.eat('=') => Token::new(TokenKind::LT_EQ, "<="),
+                '<' if self.eat('<') => Token::new(TokenKind::LSHIFT, "<<"),
+                '<' => Token::new(TokenKind::LT, "<"),
+                '>' if self.eat('=') => Token::new(TokenKind::GT_EQ, ">="),
+                '>' if self.eat('>') => Token::new(TokenKind::RSHIFT, ">>"),
+                '>' => Token::new(TokenKind::GT, ">"),
+                '&' if self.eat('&') => Token::new(TokenKind::AND, "&&"),
+                '&' => Token::new(TokenKind::AMPERSAND, "&"),
+                '|' if self.eat('|') => Token::new(TokenKind::OR, "||"),
+                '|' => Token::new(TokenKind::PIPE, "|"),
+                '^' => Token::new(TokenKind::CARET, "^"),
+                '~' => Token::new(TokenKind::TILDE, "~"),
+                '?' if self.eat('?') => Token::new(TokenKind::NULL_COALESCE, "??"),
+                '?' if self.eat('.') => Token::new(TokenKind::OPTIONAL_CHAIN, "?."),
+                ',' => Token::new(TokenKind::COMMA, ","),
+                ';' => Token::new(TokenKind::SEMICOLON, ";"),
+                '(' => Token::new(TokenKind::LPAREN, "("),
+                ')' => Token::new(TokenKind::RPAREN, ")"),
+                '{' => Token::new(TokenKind::LBRACE, "{"),
+                '}' => Token::new(TokenKind::RBRACE, "}"),
+                '[' => Token::new(TokenKind::LBRACKET, "["),
+                ']' => Token::new(TokenKind::RBRACKET, "]"),
+                _ if c.is_ascii_digit() => {
+                    Token::new(TokenKind::INTEGER, self.consume_integer(from))
+                }
+                _ if is_identifier_start(c) => search_keywords(self.consume_literal(from)),
+                _ => Token::new(TokenKind::ILLEGAL, self.slice(from)),
+            };
+            let span = Span {
+                start,
+                end: self.position,
+            };
+            return Some(Token { span, ..token });
         }
     }
-    num
 }
 
-fn consume_literal(input: &mut Peekable<Chars>, current_c: char) -> String {
-    let mut literal = String::from(current_c);
-    while let Some(c) = input.peek() {
-        if is_literal(*c) {
-            literal.push(*c);
-            input.next();
-        } else {
-            break;
+/// Lexes the whole input up front. Prefer iterating a `Lexer` when the tokens are consumed once.
+pub fn tokenize(input: &str) -> Vec<Token<'_>> {
+    Lexer::new(input).collect()
+}
+
+/// Turns positions back into byte offsets and the other way round, counting lines and
+/// columns as the lexer does. Only moves forward.
+#[derive(Debug, Clone)]
+pub struct Cursor<'a> {
+    input: &'a str,
+    offset: usize,
+    position: Position,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Cursor::resume(input, 0, Position::default())
+    }
+
+    pub fn resume(input: &'a str, offset: usize, position: Position) -> Self {
+        Cursor {
+            input,
+            offset,
+            position,
         }
     }
-    literal
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Moves up to `target` and returns its byte offset.
+    pub fn advance_to(&mut self, target: Position) -> usize {
+        let mut chars = self.input[self.offset..].chars();
+        while self.position < target {
+            let Some(c) = chars.next() else {
+                break;
+            };
+            self.offset += c.len_utf8();
+            advance(&mut self.position, c);
+        }
+        self.offset
+    }
+
+    /// Moves up to byte `offset` and returns its position.
+    pub fn advance_to_offset(&mut self, offset: usize) -> Position {
+        for c in self.input[self.offset..offset].chars() {
+            advance(&mut self.position, c);
+        }
+        self.offset = offset;
+        self.position
+    }
 }
 
-fn is_literal(c: char) -> bool {
-    c.is_ascii_alphabetic() || c == '_' || c.is_ascii_digit()
+fn advance(position: &mut Position, c: char) {
+    if c == '\n' {
+        position.line += 1;
+        position.column = 1;
+    } else {
+        position.column += 1;
+    }
 }
 
-fn search_keywords(literal: &str) -> Token {
-    match literal {
-        "fn" => Token::new(TokenKind::FUNCTION, literal.to_string()),
-        "let" => Token::new(TokenKind::LET, literal.to_string()),
-        "true" => Token::new(TokenKind::TRUE, literal.to_string()),
-        "false" => Token::new(TokenKind::FALSE, literal.to_string()),
-        "if" => Token::new(TokenKind::IF, literal.to_string()),
-        "else" => Token::new(TokenKind::ELSE, literal.to_string()),
-        "return" => Token::new(TokenKind::RETURN, literal.to_string()),
-        _ => Token::new(TokenKind::IDENTIFIER, literal.to_string()),
+// Identifiers follow Unicode UAX #31, with `_` also allowed as the first character.
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || is_xid_start(c)
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    is_xid_continue(c)
+}
+
+// Identifiers and keywords are interned as they are lexed, so keywords are told apart
+// by symbol rather than by comparing text.
+fn search_keywords(literal: &str) -> Token<'_> {
+    let symbol = Symbol::intern(literal);
+    let kind = match symbol {
+        kw::FN => TokenKind::FUNCTION,
+        kw::LET => TokenKind::LET,
+        kw::TRUE => TokenKind::TRUE,
+        kw::FALSE => TokenKind::FALSE,
+        kw::NULL => TokenKind::NULL,
+        kw::IF => TokenKind::IF,
+        kw::ELSE => TokenKind::ELSE,
+        kw::RETURN => TokenKind::RETURN,
+        kw::WHILE => TokenKind::WHILE,
+        kw::FOR => TokenKind::FOR,
+        kw::IN => TokenKind::IN,
+        kw::BREAK => TokenKind::BREAK,
+        kw::CONTINUE => TokenKind::CONTINUE,
+        _ => TokenKind::IDENTIFIER,
+    };
+    Token {
+        symbol: Some(symbol),
+        ..Token::new(kind, literal)
     }
 }
 
@@ -108,6 +272,7 @@ fn search_keywords(literal: &str) -> Token {
 mod tests {
     use super::*;
     use crate::token::{Token, TokenKind};
+    use std::borrow::Cow;
 
     #[test]
     fn test_tokenize() {
@@ -211,4 +376,359 @@ if ( 5 < 10 ) {
         ];
         assert_eq!(tokens, expected);
     }
+
+    #[test]
+    fn test_tokenize_comparison_and_logical_operators() {
+        let input = "a <= b >= c % d && e || f < g > h";
+        let tokens = tokenize(input);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::IDENTIFIER,
+                TokenKind::LT_EQ,
+                TokenKind::IDENTIFIER,
+                TokenKind::GT_EQ,
+                TokenKind::IDENTIFIER,
+                TokenKind::PERCENT,
+                TokenKind::IDENTIFIER,
+                TokenKind::AND,
+                TokenKind::IDENTIFIER,
+                TokenKind::OR,
+                TokenKind::IDENTIFIER,
+                TokenKind::LT,
+                TokenKind::IDENTIFIER,
+                TokenKind::GT,
+                TokenKind::IDENTIFIER,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_bitwise_operators() {
+        let input = "a & b && c | d || e ^ ~f << g >> h < i > j <= k";
+        let tokens = tokenize(input);
+
+        let literals: Vec<String> = tokens
+            .iter()
+            .filter(|t| t.kind != TokenKind::IDENTIFIER)
+            .map(|t| t.literal.to_string())
+            .collect();
+        let kinds: Vec<TokenKind> = tokens
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::IDENTIFIER)
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            literals,
+            vec!["&", "&&", "|", "||", "^", "~", "<<", ">>", "<", ">", "<="]
+        );
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::AMPERSAND,
+                TokenKind::AND,
+                TokenKind::PIPE,
+                TokenKind::OR,
+                TokenKind::CARET,
+                TokenKind::TILDE,
+                TokenKind::LSHIFT,
+                TokenKind::RSHIFT,
+                TokenKind::LT,
+                TokenKind::GT,
+                TokenKind::LT_EQ,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_assignment_operators() {
+        let input = "x = y == z; x += 1; x -= 1; x *= 2; x /= 2; xs[0] = 1;";
+        let tokens = tokenize(input);
+
+        let kinds: Vec<TokenKind> = tokens
+            .into_iter()
+            .filter(|t| !matches!(t.kind, TokenKind::IDENTIFIER | TokenKind::INTEGER))
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::ASSIGN,
+                TokenKind::EQ,
+                TokenKind::SEMICOLON,
+                TokenKind::PLUS_ASSIGN,
+                TokenKind::SEMICOLON,
+                TokenKind::MINUS_ASSIGN,
+                TokenKind::SEMICOLON,
+                TokenKind::ASTERISK_ASSIGN,
+                TokenKind::SEMICOLON,
+                TokenKind::SLASH_ASSIGN,
+                TokenKind::SEMICOLON,
+                TokenKind::LBRACKET,
+                TokenKind::RBRACKET,
+                TokenKind::ASSIGN,
+                TokenKind::SEMICOLON,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_loop_keywords() {
+        let input = "while for in break continue inner";
+        let tokens = tokenize(input);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::WHILE,
+                TokenKind::FOR,
+                TokenKind::IN,
+                TokenKind::BREAK,
+                TokenKind::CONTINUE,
+                TokenKind::IDENTIFIER,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_null_operators() {
+        let input = "null ?? a?.[0] ?? f?.(x) ? nullable";
+        let tokens = tokenize(input);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::NULL,
+                TokenKind::NULL_COALESCE,
+                TokenKind::IDENTIFIER,
+                TokenKind::OPTIONAL_CHAIN,
+                TokenKind::LBRACKET,
+                TokenKind::INTEGER,
+                TokenKind::RBRACKET,
+                TokenKind::NULL_COALESCE,
+                TokenKind::IDENTIFIER,
+                TokenKind::OPTIONAL_CHAIN,
+                TokenKind::LPAREN,
+                TokenKind::IDENTIFIER,
+                TokenKind::RPAREN,
+                TokenKind::ILLEGAL,
+                TokenKind::IDENTIFIER,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unicode_identifiers() {
+        let input = "let 合計 = größe + _x1 + café_2;";
+        let tokens = tokenize(input);
+
+        let expected = vec![
+            Token::new(TokenKind::LET, String::from("let")),
+            Token::new(TokenKind::IDENTIFIER, String::from("合計")),
+            Token::new(TokenKind::ASSIGN, String::from("=")),
+            Token::new(TokenKind::IDENTIFIER, String::from("größe")),
+            Token::new(TokenKind::PLUS, String::from("+")),
+            Token::new(TokenKind::IDENTIFIER, String::from("_x1")),
+            Token::new(TokenKind::PLUS, String::from("+")),
+            Token::new(TokenKind::IDENTIFIER, String::from("café_2")),
+            Token::new(TokenKind::SEMICOLON, String::from(";")),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_tokenize_non_identifier_characters() {
+        let tokens = tokenize("a😀b ·x");
+
+        let kinds: Vec<(TokenKind, String)> = tokens
+            .into_iter()
+            .map(|t| (t.kind, t.literal.into_owned()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (TokenKind::IDENTIFIER, String::from("a")),
+                (TokenKind::ILLEGAL, String::from("😀")),
+                (TokenKind::IDENTIFIER, String::from("b")),
+                (TokenKind::ILLEGAL, String::from("·")),
+                (TokenKind::IDENTIFIER, String::from("x")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_strings_and_comments() {
+        let input = r#"
+// 合計を計算する
+let s = "こんにちは, 世界 🌍"; // trailing comment
+"a \"quoted\" word" / 2;
+"unterminated"#;
+        let tokens = tokenize(input);
+
+        let expected = vec![
+            Token::new(TokenKind::LET, String::from("let")),
+            Token::new(TokenKind::IDENTIFIER, String::from("s")),
+            Token::new(TokenKind::ASSIGN, String::from("=")),
+            Token::new(TokenKind::STRING, String::from("こんにちは, 世界 🌍")),
+            Token::new(TokenKind::SEMICOLON, String::from(";")),
+            Token::new(TokenKind::STRING, String::from(r#"a \"quoted\" word"#)),
+            Token::new(TokenKind::SLASH, String::from("/")),
+            Token::new(TokenKind::INTEGER, String::from("2")),
+            Token::new(TokenKind::SEMICOLON, String::from(";")),
+            Token::new(TokenKind::ILLEGAL, String::from("\"unterminated")),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_token_spans_count_characters() {
+        let input = "let 合計 = \"日本\"; größe\n// ü\nx";
+        let tokens = tokenize(input);
+
+        let spans: Vec<(usize, usize, usize)> = tokens
+            .iter()
+            .map(|t| (t.span.start.line, t.span.start.column, t.span.end.column))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (1, 1, 4),
+                (1, 5, 7),
+                (1, 8, 9),
+                (1, 10, 14),
+                (1, 14, 15),
+                (1, 16, 21),
+                (3, 1, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_integer_literals() {
+        let input = "0xFF 0b1010 0o755 1_000_000 0b102 12abc 7;";
+        let tokens = tokenize(input);
+
+        let expected = vec![
+            Token::new(TokenKind::INTEGER, String::from("0xFF")),
+            Token::new(TokenKind::INTEGER, String::from("0b1010")),
+            Token::new(TokenKind::INTEGER, String::from("0o755")),
+            Token::new(TokenKind::INTEGER, String::from("1_000_000")),
+            Token::new(TokenKind::INTEGER, String::from("0b102")),
+            Token::new(TokenKind::INTEGER, String::from("12abc")),
+            Token::new(TokenKind::INTEGER, String::from("7")),
+            Token::new(TokenKind::SEMICOLON, String::from(";")),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 10;\n  x == 5";
+        let tokens = tokenize(input);
+
+        let spans: Vec<(usize, usize, usize)> = tokens
+            .iter()
+            .map(|t| (t.span.start.line, t.span.start.column, t.span.end.column))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (1, 1, 4),
+                (1, 5, 6),
+                (1, 7, 8),
+                (1, 9, 11),
+                (1, 11, 12),
+                (2, 3, 4),
+                (2, 5, 7),
+                (2, 8, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_is_lazy_and_borrows_literals() {
+        let input = "let größe = \"a\\\"b\" + 0x1F; $";
+        let mut lexer = Lexer::new(input);
+
+        let first = lexer.next().unwrap();
+        assert_eq!(first, Token::new(TokenKind::LET, "let"));
+
+        let rest: Vec<Token> = lexer.collect();
+        for token in &rest {
+            assert!(
+                matches!(token.literal, Cow::Borrowed(_)),
+                "{} was copied",
+                token
+            );
+        }
+        let literals: Vec<&str> = rest.iter().map(|t| t.literal.as_ref()).collect();
+        assert_eq!(
+            literals,
+            vec!["größe", "=", "a\\\"b", "+", "0x1F", ";", "$"]
+        );
+        assert_eq!(rest.last().unwrap().kind, TokenKind::ILLEGAL);
+    }
+
+    #[test]
+    fn test_identifiers_and_keywords_are_interned() {
+        let tokens = tokenize("let total = total + 1;");
+
+        let symbols: Vec<Option<Symbol>> = tokens.iter().map(|t| t.symbol).collect();
+        let total = Symbol::intern("total");
+        assert_eq!(
+            symbols,
+            vec![
+                Some(kw::LET),
+                Some(total),
+                None,
+                Some(total),
+                None,
+                None,
+                None
+            ]
+        );
+
+        let owned = tokens[1].clone().into_owned();
+        assert_eq!(owned.literal, "total");
+        assert_eq!(owned.symbol, Some(total));
+    }
+
+    #[test]
+    fn test_symbols_outlive_the_lexer() {
+        let first = tokenize("alpha beta");
+        let second = tokenize("gamma");
+
+        assert_ne!(first[0].symbol, second[0].symbol);
+        assert_ne!(first[1].symbol, second[0].symbol);
+        assert_eq!(format!("{:?}", first[0].symbol), r#"Some(Symbol("alpha"))"#);
+        assert_eq!(
+            format!("{:?}", second[0].symbol),
+            r#"Some(Symbol("gamma"))"#
+        );
+    }
+
+    #[test]
+    fn test_fixed_spellings() {
+        let input =
+            "= += -= *= /= + - ! * / % < > <= >= == != && || & | ^ ~ << >> ?? ?. , ; ( ) { }
+            [ ] fn let true false null if else return while for in break continue x 1 \"s\" $";
+        let tokens = tokenize(input);
+        assert_eq!(tokens.len(), 52);
+
+        for token in tokens {
+            let owned = token.clone().into_owned();
+            match token.kind.spelling() {
+                Some(spelling) => {
+                    assert_eq!(token.literal, spelling);
+                    assert!(matches!(owned.literal, Cow::Borrowed(_)), "{:?}", token);
+                }
+                None => assert!(matches!(owned.literal, Cow::Owned(_)), "{:?}", token),
+            }
+            assert_eq!(owned.literal, token.literal);
+        }
+    }
 }
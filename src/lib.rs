@@ -0,0 +1,39 @@
+//! An interpreter for the Monkey programming language.
+//!
+//! [`Interpreter`] is the way to run Monkey code from Rust, and the types it takes and
+//! returns are exported alongside it. Everything else is internal.
+
+pub(crate) mod ast;
+#[doc(hidden)]
+pub mod dap;
+#[doc(hidden)]
+pub mod debugger;
+pub(crate) mod document;
+pub(crate) mod environment;
+pub(crate) mod evaluator;
+pub(crate) mod gc;
+#[doc(hidden)]
+pub mod highlight;
+pub(crate) mod interpreter;
+#[doc(hidden)]
+pub mod lexer;
+pub(crate) mod limits;
+#[doc(hidden)]
+pub mod lsp;
+pub(crate) mod object;
+pub(crate) mod optimizer;
+#[doc(hidden)]
+pub mod parser;
+#[doc(hidden)]
+pub mod profiler;
+#[doc(hidden)]
+pub mod repl;
+pub(crate) mod resolver;
+pub(crate) mod symbol;
+#[doc(hidden)]
+pub mod token;
+
+pub use evaluator::IntegerMode;
+pub use interpreter::{Error, Interpreter};
+pub use limits::{Limit, Limits};
+pub use object::Object;
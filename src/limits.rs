@@ -0,0 +1,76 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// The native stack a run may use by default. It leaves room to spare in the 2 MiB Rust gives
+/// spawned threads.
+pub const DEFAULT_STACK: usize = 1 << 20;
+
+/// The bytes a string or array may take by default.
+pub const DEFAULT_SIZE: usize = 1 << 30;
+
+/// Bounds on the resources a program may use, for running code that can't be trusted.
+/// Each is unlimited when `None`, apart from the stack and the size of values, which always
+/// have a bound. A program
+/// that exceeds one stops with `Object::LimitExceeded` rather than an ordinary error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Statements and expressions evaluated.
+    pub steps: Option<u64>,
+    /// Function calls in progress at once. Tail calls replace the call making them, so they
+    /// don't count.
+    pub call_depth: Option<usize>,
+    /// Environments and arrays alive at once.
+    pub objects: Option<usize>,
+    /// Time spent running the program.
+    pub time: Option<Duration>,
+    /// Bits an arbitrary precision integer may take. Results are checked before they are
+    /// computed, since computing a huge one can take longer than any other limit allows.
+    pub integer_bits: Option<u64>,
+    /// Bytes of native stack the evaluator may use. Evaluation recurses as calls and
+    /// expressions nest, so this is always bounded, whatever `call_depth` allows. It must
+    /// fit in the stack of the thread running the program.
+    pub stack: usize,
+    /// Bytes a single string or array may take. It is checked before one is built, so a
+    /// program that keeps doubling a string stops here rather than when allocating fails.
+    pub size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            steps: None,
+            call_depth: None,
+            objects: None,
+            time: None,
+            integer_bits: None,
+            stack: DEFAULT_STACK,
+            size: DEFAULT_SIZE,
+        }
+    }
+}
+
+/// The limit a program exceeded, with its configured value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64),
+    CallDepth(usize),
+    Objects(usize),
+    Time(Duration),
+    IntegerBits(u64),
+    Stack(usize),
+    Size(usize),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps(steps) => write!(f, "took more than {} steps", steps),
+            Limit::CallDepth(depth) => write!(f, "calls nested more than {} deep", depth),
+            Limit::Objects(objects) => write!(f, "more than {} objects alive", objects),
+            Limit::Time(time) => write!(f, "ran for more than {:?}", time),
+            Limit::IntegerBits(bits) => write!(f, "an integer over {} bits", bits),
+            Limit::Stack(bytes) => write!(f, "nested too deep for {} bytes of stack", bytes),
+            Limit::Size(bytes) => write!(f, "a string or array over {} bytes", bytes),
+        }
+    }
+}
@@ -0,0 +1,756 @@
+use crate::ast::*;
+use crate::document::Document;
+use crate::lexer::Cursor;
+use crate::parser::ParseError;
+use crate::resolver::{Declaration, Declarer, Reference, Resolution, Resolver};
+use crate::symbol::KEYWORDS;
+use crate::token::{Position, Span};
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+
+const COMPLETION_FUNCTION: u8 = 3;
+const COMPLETION_VARIABLE: u8 = 6;
+const COMPLETION_KEYWORD: u8 = 14;
+
+const SYMBOL_FUNCTION: u8 = 12;
+const SYMBOL_VARIABLE: u8 = 13;
+
+/// Serves the Language Server Protocol over `input` and `output` until the client sends
+/// `exit` or closes the stream.
+///
+/// Documents are synced incrementally and only reparsed around each edit, but names are
+/// resolved afresh for each request.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Messages are JSON bodies behind a `Content-Length` header. The debug adapter protocol
+// frames its messages the same way.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[derive(Debug, Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    exited: bool,
+}
+
+impl Server {
+    // Returns the messages to send back: the response to a request, and any notifications.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // We never send requests, so there are no responses to wait for.
+            return Vec::new();
+        };
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notify(method, params);
+        };
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 2,
+                    "completionProvider": {},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "monkey" },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/completion" => self.with_document(params, completion),
+            "textDocument/hover" => self.with_document(params, hover),
+            "textDocument/definition" => self.with_document(params, definition),
+            "textDocument/references" => self.with_document(params, references),
+            "textDocument/documentSymbol" => self.with_document(params, document_symbols),
+            _ => {
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("unknown method: {}", method),
+                    },
+                })]
+            }
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), Document::new(text));
+            }
+            // Changes come in order, each against the text the one before it left. One
+            // without a range replaces the whole text.
+            "textDocument/didChange" => {
+                let Some(document) = self.documents.get_mut(uri) else {
+                    return Vec::new();
+                };
+                let changes = params["contentChanges"].as_array();
+                for change in changes.into_iter().flatten() {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    match change.get("range") {
+                        Some(range) => {
+                            let start = to_offset(document.text(), &range["start"]);
+                            let end = to_offset(document.text(), &range["end"]).max(start);
+                            document.edit(start..end, text);
+                        }
+                        None => *document = Document::new(text),
+                    }
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            "exit" => {
+                self.exited = true;
+                return Vec::new();
+            }
+            _ => return Vec::new(),
+        }
+
+        let document = &self.documents[uri];
+        vec![publish_diagnostics(
+            uri,
+            Analysis::new(document).diagnostics(),
+        )]
+    }
+
+    // Requests about documents we haven't been sent get a `null` result.
+    fn with_document(
+        &self,
+        params: &Value,
+        request: fn(&Analysis, &Value, Position) -> Value,
+    ) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let Some(document) = self.documents.get(uri) else {
+            return Value::Null;
+        };
+        let analysis = Analysis::new(document);
+        let position = from_lsp_position(document.text(), &params["position"]);
+        request(&analysis, params, position)
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+struct Analysis<'a> {
+    text: &'a str,
+    program: Program,
+    parse_errors: Vec<&'a ParseError>,
+    resolution: Resolution,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(document: &'a Document) -> Self {
+        let mut program = document.program();
+        let resolution = Resolver::new().resolve(&mut program);
+        Analysis {
+            text: document.text(),
+            program,
+            parse_errors: document.errors().collect(),
+            resolution,
+        }
+    }
+
+    // Name errors in a program that didn't parse are mostly noise, so they wait until it does.
+    fn diagnostics(&self) -> Vec<Value> {
+        if !self.parse_errors.is_empty() {
+            return self
+                .parse_errors
+                .iter()
+                .map(|error| self.diagnostic(error.span(), SEVERITY_ERROR, error.message()))
+                .collect();
+        }
+        self.resolution
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let severity = if diagnostic.is_error() {
+                    SEVERITY_ERROR
+                } else {
+                    SEVERITY_WARNING
+                };
+                self.diagnostic(diagnostic.span(), severity, diagnostic.message())
+            })
+            .collect()
+    }
+
+    fn diagnostic(&self, span: Span, severity: u8, message: String) -> Value {
+        json!({
+            "range": self.range(span),
+            "severity": severity,
+            "source": "monkey",
+            "message": message,
+        })
+    }
+
+    fn reference_at(&self, position: Position) -> Option<&Reference> {
+        self.resolution
+            .references
+            .iter()
+            .find(|reference| reference.span.start <= position && position <= reference.span.end)
+    }
+
+    // Declarations made before `position` in a scope that contains it, latest first. Only
+    // the latest of several with the same name is kept, as it shadows the others.
+    fn visible_declarations(&self, position: Position) -> Vec<Declaration> {
+        let mut declarations: Vec<Declaration> = self
+            .resolution
+            .references
+            .iter()
+            .filter(|reference| reference.span == reference.declaration.span)
+            .map(|reference| reference.declaration)
+            .filter(|declaration| declaration.span.start < position)
+            .filter(|declaration| match declaration.scope {
+                Some(block) => {
+                    let block = &self.program.ast[block];
+                    block.token.span.start <= position && position <= block.end.start
+                }
+                None => true,
+            })
+            .collect();
+        declarations.sort_by_key(|declaration| Reverse(declaration.span.start));
+
+        let mut names = HashSet::new();
+        declarations.retain(|declaration| names.insert(declaration.name));
+        declarations
+    }
+
+    // The code that declares the binding, as shown in hovers and completions.
+    fn signature(&self, declaration: &Declaration) -> String {
+        let ast = &self.program.ast;
+        match declaration.declarer {
+            Declarer::Let(id) => ast[id].string(ast),
+            Declarer::Parameter(id) => match ast.expression(id) {
+                ExpressionNode::FunctionLiteral(function) => {
+                    let parameters: Vec<String> = function
+                        .parameters
+                        .iter()
+                        .map(|parameter| parameter.value.to_string())
+                        .collect();
+                    format!("fn({})", parameters.join(", "))
+                }
+                _ => String::new(),
+            },
+            Declarer::LoopVariable(id) => match ast.statement(id) {
+                StatementNode::ForStatement(statement) => format!(
+                    "for ({} in {})",
+                    statement.variable.value,
+                    ast[statement.iterable].string(ast)
+                ),
+                _ => String::new(),
+            },
+            Declarer::Host => declaration.name.to_string(),
+        }
+    }
+
+    fn is_function(&self, declaration: &Declaration) -> bool {
+        let Declarer::Let(id) = declaration.declarer else {
+            return false;
+        };
+        let ast = &self.program.ast;
+        match ast.statement(id) {
+            StatementNode::LetStatement(statement) => matches!(
+                ast.expression(statement.value),
+                ExpressionNode::FunctionLiteral(_)
+            ),
+            _ => false,
+        }
+    }
+
+    fn range(&self, span: Span) -> Value {
+        json!({
+            "start": to_lsp_position(self.text, span.start),
+            "end": to_lsp_position(self.text, span.end),
+        })
+    }
+
+    fn location(&self, params: &Value, span: Span) -> Value {
+        json!({ "uri": params["textDocument"]["uri"], "range": self.range(span) })
+    }
+}
+
+fn completion(analysis: &Analysis, _params: &Value, position: Position) -> Value {
+    let keywords = KEYWORDS
+        .iter()
+        .map(|keyword| json!({ "label": keyword, "kind": COMPLETION_KEYWORD }));
+    let identifiers = analysis
+        .visible_declarations(position)
+        .into_iter()
+        .map(|declaration| {
+            let kind = if analysis.is_function(&declaration) {
+                COMPLETION_FUNCTION
+            } else {
+                COMPLETION_VARIABLE
+            };
+            json!({
+                "label": declaration.name.to_string(),
+                "kind": kind,
+                "detail": analysis.signature(&declaration),
+            })
+        });
+    Value::Array(identifiers.chain(keywords).collect())
+}
+
+fn hover(analysis: &Analysis, _params: &Value, position: Position) -> Value {
+    let Some(reference) = analysis.reference_at(position) else {
+        return Value::Null;
+    };
+    let declaration = &reference.declaration;
+
+    let mut contents = format!("```monkey\n{}\n```", analysis.signature(declaration));
+    match declaration.declarer {
+        Declarer::Let(_) | Declarer::Host => {}
+        Declarer::Parameter(_) => contents += &format!("\nparameter `{}`", declaration.name),
+        Declarer::LoopVariable(_) => contents += &format!("\nloop variable `{}`", declaration.name),
+    }
+    json!({
+        "contents": { "kind": "markdown", "value": contents },
+        "range": analysis.range(reference.span),
+    })
+}
+
+fn definition(analysis: &Analysis, params: &Value, position: Position) -> Value {
+    match analysis.reference_at(position) {
+        Some(reference) => analysis.location(params, reference.declaration.span),
+        None => Value::Null,
+    }
+}
+
+fn references(analysis: &Analysis, params: &Value, position: Position) -> Value {
+    let Some(target) = analysis.reference_at(position) else {
+        return Value::Null;
+    };
+    let include_declaration = params["context"]["includeDeclaration"]
+        .as_bool()
+        .unwrap_or(true);
+
+    let locations = analysis
+        .resolution
+        .references
+        .iter()
+        .filter(|reference| reference.declaration == target.declaration)
+        .filter(|reference| include_declaration || reference.span != reference.declaration.span)
+        .map(|reference| analysis.location(params, reference.span))
+        .collect();
+    Value::Array(locations)
+}
+
+fn document_symbols(analysis: &Analysis, _params: &Value, _position: Position) -> Value {
+    let ast = &analysis.program.ast;
+    let symbols = analysis
+        .program
+        .statements
+        .iter()
+        .filter_map(|id| match ast.statement(*id) {
+            StatementNode::LetStatement(statement) => Some(statement),
+            _ => None,
+        })
+        .map(|statement| {
+            let kind = match ast.expression(statement.value) {
+                ExpressionNode::FunctionLiteral(_) => SYMBOL_FUNCTION,
+                _ => SYMBOL_VARIABLE,
+            };
+            let name = statement.name.token.span;
+            json!({
+                "name": statement.name.value.to_string(),
+                "kind": kind,
+                "range": analysis.range(Span {
+                    start: statement.token.span.start,
+                    end: name.end,
+                }),
+                "selectionRange": analysis.range(name),
+            })
+        })
+        .collect();
+    Value::Array(symbols)
+}
+
+// The protocol counts lines and UTF-16 code units from 0, where we count lines and
+// characters from 1.
+fn to_lsp_position(text: &str, position: Position) -> Value {
+    let line = text.lines().nth(position.line - 1).unwrap_or_default();
+    let character: usize = line
+        .chars()
+        .take(position.column - 1)
+        .map(char::len_utf16)
+        .sum();
+    json!({ "line": position.line - 1, "character": character })
+}
+
+fn from_lsp_position(text: &str, position: &Value) -> Position {
+    let line = position["line"].as_u64().unwrap_or_default() as usize;
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+
+    let mut units = 0;
+    let mut column = 1;
+    for c in text.lines().nth(line).unwrap_or_default().chars() {
+        if units >= character {
+            break;
+        }
+        units += c.len_utf16();
+        column += 1;
+    }
+    Position {
+        line: line + 1,
+        column,
+    }
+}
+
+fn to_offset(text: &str, position: &Value) -> usize {
+    Cursor::new(text).advance_to(from_lsp_position(text, position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.mk";
+
+    // Runs the server over `messages` and returns everything it sent back.
+    fn exchange(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        run(&input[..], &mut output).unwrap();
+
+        let mut output = &output[..];
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": URI, "languageId": "monkey", "version": 1, "text": text },
+            },
+        })
+    }
+
+    fn request(id: u64, method: &str, line: u64, character: u64) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": true },
+            },
+        })
+    }
+
+    // The result of the only request sent after opening `text`.
+    fn result(text: &str, request: Value) -> Value {
+        let replies = exchange(&[open(text), request]);
+        replies[1]["result"].clone()
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 },
+        })
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let replies = exchange(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "workspace/symbol", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "shutdown" }),
+        ]);
+
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(
+            replies[0]["result"]["capabilities"]["definitionProvider"],
+            true
+        );
+        assert_eq!(replies[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(
+            replies[2],
+            json!({ "jsonrpc": "2.0", "id": 3, "result": null })
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_follow_changes() {
+        let change = |text: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": URI, "version": 2 },
+                    "contentChanges": [{ "text": text }],
+                },
+            })
+        };
+        let replies = exchange(&[
+            open("let x = ;"),
+            change("let x = 1;\nx + y"),
+            change("let x = 1;\nlet f = fn(x) { x };"),
+            change("let x = 1;"),
+        ]);
+
+        let diagnostics: Vec<&Value> = replies
+            .iter()
+            .map(|reply| {
+                assert_eq!(reply["method"], "textDocument/publishDiagnostics");
+                assert_eq!(reply["params"]["uri"], URI);
+                &reply["params"]["diagnostics"]
+            })
+            .collect();
+        assert_eq!(diagnostics[0][0]["range"], range((0, 8), (0, 9)));
+        assert_eq!(diagnostics[0][0]["severity"], SEVERITY_ERROR);
+        assert_eq!(
+            diagnostics[0][0]["message"],
+            "Unexpected token: Token{ kind: ;, literal: ; }"
+        );
+        assert_eq!(
+            *diagnostics[1],
+            json!([{
+                "range": range((1, 4), (1, 5)),
+                "severity": SEVERITY_ERROR,
+                "source": "monkey",
+                "message": "undefined identifier: y",
+            }])
+        );
+        assert_eq!(diagnostics[2][0]["severity"], SEVERITY_WARNING);
+        assert_eq!(
+            diagnostics[2][0]["message"],
+            "x shadows the binding declared at 1:5"
+        );
+        assert_eq!(*diagnostics[3], json!([]));
+    }
+
+    #[test]
+    fn test_ranged_changes() {
+        let change = |changes: Value| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": URI, "version": 2 },
+                    "contentChanges": changes,
+                },
+            })
+        };
+        let replies = exchange(&[
+            open("let s = \"😀\";\nlet x = 1;\nx + y"),
+            change(json!([{ "range": range((2, 4), (2, 5)), "text": "s" }])),
+            // The second change is against the text the first one left.
+            change(json!([
+                { "range": range((0, 8), (0, 12)), "text": "2" },
+                { "range": range((0, 9), (0, 9)), "text": " + z" },
+            ])),
+        ]);
+
+        assert_eq!(
+            replies[0]["params"]["diagnostics"][0]["message"],
+            "undefined identifier: y"
+        );
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+        assert_eq!(
+            replies[2]["params"]["diagnostics"][0]["range"],
+            range((0, 12), (0, 13))
+        );
+        assert_eq!(
+            replies[2]["params"]["diagnostics"][0]["message"],
+            "undefined identifier: z"
+        );
+    }
+
+    const PROGRAM: &str = "let add = fn(a, b) { a + b };
+let total = add(1, 2);
+for (n in [total]) { add(n, 3) }";
+
+    #[test]
+    fn test_hover() {
+        let hover = result(PROGRAM, request(1, "textDocument/hover", 1, 13));
+        assert_eq!(
+            hover["contents"]["value"],
+            "```monkey\nlet add = fn(a, b)(a + b);\n```"
+        );
+        assert_eq!(hover["range"], range((1, 12), (1, 15)));
+
+        let hover = result(PROGRAM, request(1, "textDocument/hover", 0, 21));
+        assert_eq!(
+            hover["contents"]["value"],
+            "```monkey\nfn(a, b)\n```\nparameter `a`"
+        );
+
+        let hover = result(PROGRAM, request(1, "textDocument/hover", 2, 25));
+        assert_eq!(
+            hover["contents"]["value"],
+            "```monkey\nfor (n in [total])\n```\nloop variable `n`"
+        );
+
+        let hover = result(PROGRAM, request(1, "textDocument/hover", 1, 17));
+        assert_eq!(hover, Value::Null);
+    }
+
+    #[test]
+    fn test_definition_and_references() {
+        let definition = result(PROGRAM, request(1, "textDocument/definition", 0, 26));
+        assert_eq!(
+            definition,
+            json!({ "uri": URI, "range": range((0, 16), (0, 17)) })
+        );
+
+        let references = result(PROGRAM, request(1, "textDocument/references", 2, 21));
+        let ranges: Vec<&Value> = references
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| &location["range"])
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                &range((0, 4), (0, 7)),
+                &range((1, 12), (1, 15)),
+                &range((2, 21), (2, 24)),
+            ]
+        );
+
+        let mut without_declaration = request(1, "textDocument/references", 0, 5);
+        without_declaration["params"]["context"]["includeDeclaration"] = json!(false);
+        let references = result(PROGRAM, without_declaration);
+        assert_eq!(references.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_completion() {
+        let labels = |line, character| {
+            let items = result(
+                PROGRAM,
+                request(1, "textDocument/completion", line, character),
+            );
+            items
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Inside the function body its parameters are in scope, and so is `add` itself.
+        let inside = labels(0, 21);
+        assert_eq!(inside[..3], ["b", "a", "add"]);
+        assert!(inside.contains(&"let".to_string()));
+        assert!(!inside.contains(&"total".to_string()));
+
+        let after = labels(2, 21);
+        assert_eq!(after[..3], ["n", "total", "add"]);
+        assert_eq!(after.len(), 3 + KEYWORDS.len());
+
+        let items = result(PROGRAM, request(1, "textDocument/completion", 1, 0));
+        assert_eq!(items[0]["label"], "add");
+        assert_eq!(items[0]["kind"], COMPLETION_FUNCTION);
+        assert_eq!(items[0]["detail"], "let add = fn(a, b)(a + b);");
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let symbols = result(PROGRAM, request(1, "textDocument/documentSymbol", 0, 0));
+        assert_eq!(
+            symbols,
+            json!([
+                {
+                    "name": "add",
+                    "kind": SYMBOL_FUNCTION,
+                    "range": range((0, 0), (0, 7)),
+                    "selectionRange": range((0, 4), (0, 7)),
+                },
+                {
+                    "name": "total",
+                    "kind": SYMBOL_VARIABLE,
+                    "range": range((1, 0), (1, 9)),
+                    "selectionRange": range((1, 4), (1, 9)),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_positions_count_utf16_code_units() {
+        let text = "let s = \"😀\"; let t = s;";
+        let definition = result(text, request(1, "textDocument/definition", 0, 22));
+        assert_eq!(definition["range"], range((0, 4), (0, 5)));
+
+        let references = result(text, request(1, "textDocument/references", 0, 4));
+        assert_eq!(references[1]["range"], range((0, 22), (0, 23)));
+    }
+}
@@ -1,13 +1,175 @@
-mod ast;
-mod lexer;
-mod parser;
-mod repl;
-mod token;
+use monkey::repl::start;
+use monkey::{dap, debugger, highlight, lsp, profiler, IntegerMode, Limits};
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::time::Duration;
+use std::{fs, process, thread};
 
-use crate::repl::start;
+// The stack programs run on. Evaluation recurses as programs nest, so the REPL gets a much
+// bigger stack than the main thread's to allow deep recursion.
+const STACK_SIZE: usize = 256 << 20;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let cli = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run(args))
+        .expect("spawning the interpreter's thread");
+    if cli.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run(args: Vec<String>) {
+    match args.get(1).map(String::as_str) {
+        Some("lsp") => {
+            if let Err(error) = lsp::run(io::stdin().lock(), io::stdout().lock()) {
+                fail("lsp", error);
+            }
+            return;
+        }
+        Some("dap") => {
+            let result = limits(&args).and_then(|limits| {
+                dap::run(
+                    integer_mode(&args),
+                    limits,
+                    io::stdin().lock(),
+                    io::stdout(),
+                )
+            });
+            if let Err(error) = result {
+                fail("dap", error);
+            }
+            return;
+        }
+        Some("debug") => {
+            if let Err(error) = debug(&args[2..]) {
+                fail("debug", error);
+            }
+            return;
+        }
+        Some("highlight") => {
+            if let Err(error) = highlight(&args[2..]) {
+                fail("highlight", error);
+            }
+            return;
+        }
+        Some("profile") => {
+            if let Err(error) = profile(&args[2..]) {
+                fail("profile", error);
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let optimize = args.iter().any(|arg| arg == "--optimize");
+    let limits = match limits(&args) {
+        Ok(limits) => limits,
+        Err(error) => fail("repl", error),
+    };
+
     println!("Hello,! This is the Monkey programming language!");
     println!("Feel free to type in commands");
-    start();
+    start(integer_mode(&args), limits, optimize);
+}
+
+fn integer_mode(args: &[String]) -> IntegerMode {
+    if args.iter().any(|arg| arg == "--wrapping") {
+        IntegerMode::Wrapping
+    } else if args.iter().any(|arg| arg == "--checked") {
+        IntegerMode::Checked
+    } else {
+        IntegerMode::Arbitrary
+    }
+}
+
+// `--max-steps=N`, `--max-depth=N`, `--max-objects=N`, `--max-integer-bits=N`,
+// `--max-size=BYTES` and `--timeout=MS` limit what each input may use.
+fn limits(args: &[String]) -> io::Result<Limits> {
+    let timeout: Option<u64> = flag(args, "--timeout=")?;
+    Ok(Limits {
+        steps: flag(args, "--max-steps=")?,
+        call_depth: flag(args, "--max-depth=")?,
+        objects: flag(args, "--max-objects=")?,
+        time: timeout.map(Duration::from_millis),
+        integer_bits: flag(args, "--max-integer-bits=")?,
+        // What is left is for the CLI's own calls.
+        stack: STACK_SIZE - (1 << 20),
+        size: flag(args, "--max-size=")?.unwrap_or(Limits::default().size),
+    })
+}
+
+fn flag<T: FromStr>(args: &[String], prefix: &str) -> io::Result<Option<T>> {
+    let Some(value) = args.iter().find_map(|arg| arg.strip_prefix(prefix)) else {
+        return Ok(None);
+    };
+    match value.parse() {
+        Ok(value) => Ok(Some(value)),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "invalid value for {}: {}",
+                prefix.trim_end_matches('='),
+                value
+            ),
+        )),
+    }
+}
+
+// `monkey debug FILE` runs FILE under the debugger, taking commands from standard input.
+fn debug(args: &[String]) -> io::Result<()> {
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: monkey debug FILE",
+        ));
+    };
+    let source = fs::read_to_string(path)?;
+    debugger::run(
+        &source,
+        integer_mode(args),
+        limits(args)?,
+        io::stdin().lock(),
+        io::stdout(),
+    )
+}
+
+// `monkey profile [--folded] FILE` runs FILE and prints how long each function took, or
+// the folded stacks a flamegraph is drawn from.
+fn profile(args: &[String]) -> io::Result<()> {
+    let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: monkey profile [--folded] FILE",
+        ));
+    };
+    let source = fs::read_to_string(path)?;
+    let folded = args.iter().any(|arg| arg == "--folded");
+    profiler::run(&source, integer_mode(args), folded, io::stdout().lock())
+}
+
+// `monkey highlight [--html] [FILE]` prints FILE, or standard input, highlighted.
+fn highlight(args: &[String]) -> io::Result<()> {
+    let html = args.iter().any(|arg| arg == "--html");
+    let source = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            source
+        }
+    };
+
+    if html {
+        println!("{}", highlight::to_html(&source));
+    } else {
+        print!("{}", highlight::to_ansi(&source));
+    }
+    Ok(())
+}
+
+fn fail(command: &str, error: io::Error) -> ! {
+    eprintln!("monkey {}: {}", command, error);
+    process::exit(1);
 }
@@ -0,0 +1,283 @@
+use crate::ast::{Ast, BlockId, ExpressionId, Identifier, Node};
+use crate::environment::Environment;
+use crate::limits::Limit;
+use crate::symbol::Symbol;
+use num_bigint::BigInt;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Display};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+/// A Monkey value. Hosts get these back from an `Interpreter` and pass them in as globals and
+/// arguments. More kinds of value may be added, so matches on it need a wildcard arm.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Object {
+    Integer(i64),
+    /// An integer outside the `i64` range. Values that fit are always stored as `Integer`.
+    BigInteger(BigInt),
+    Boolean(bool),
+    String(String),
+    Null,
+    // The evaluator's control flow, which never escapes the program it happens in.
+    #[doc(hidden)]
+    ReturnValue(Box<Object>),
+    /// Unwinds to the innermost enclosing loop.
+    #[doc(hidden)]
+    Break,
+    #[doc(hidden)]
+    Continue,
+    /// A call in tail position, left for the function's caller to make once the function
+    /// has returned.
+    #[doc(hidden)]
+    TailCall(Box<TailCall>),
+    /// Arrays are shared by reference, so index assignment is visible through every binding.
+    Array(Rc<RefCell<Array>>),
+    Function(Function),
+    Builtin(Builtin),
+    Error(String),
+    /// Stops the program like an error, but is the program's resources running out rather
+    /// than a mistake in it.
+    LimitExceeded(Limit),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) | Object::BigInteger(_) => "INTEGER",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Break => "BREAK",
+            Object::Continue => "CONTINUE",
+            Object::TailCall(_) => "TAIL_CALL",
+            Object::Array(_) => "ARRAY",
+            Object::Function(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Error(_) => "ERROR",
+            Object::LimitExceeded(_) => "LIMIT_EXCEEDED",
+        }
+    }
+
+    /// Whether this stops the program, as errors and exceeded limits both do.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Object::Error(_) | Object::LimitExceeded(_))
+    }
+
+    /// Whether this unwinds the expressions and statements around it rather than being a
+    /// value, so whatever evaluated it must hand it straight back.
+    pub fn is_unwinding(&self) -> bool {
+        self.is_error()
+            || matches!(
+                self,
+                Object::ReturnValue(_) | Object::Break | Object::Continue
+            )
+    }
+
+    /// Stores `value` as an `Integer` when it fits, so both variants never hold the same number.
+    pub fn from_big_integer(value: BigInt) -> Object {
+        match i64::try_from(&value) {
+            Ok(value) => Object::Integer(value),
+            Err(_) => Object::BigInteger(value),
+        }
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            Object::BigInteger(value) => write!(f, "{}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::String(value) => write!(f, "{}", value),
+            Object::Null => write!(f, "null"),
+            Object::ReturnValue(value) => write!(f, "{}", value),
+            Object::Break => write!(f, "break"),
+            Object::Continue => write!(f, "continue"),
+            Object::TailCall(call) => write!(f, "tail call to {}", call.function),
+            Object::Array(elements) => write_array(f, elements),
+            Object::Function(function) => write!(f, "{}", function),
+            Object::Builtin(builtin) => write!(f, "builtin {}", builtin.name()),
+            Object::Error(message) => write!(f, "ERROR: {}", message),
+            Object::LimitExceeded(limit) => write!(f, "LIMIT EXCEEDED: {}", limit),
+        }
+    }
+}
+
+// Arrays can nest arbitrarily deep and can contain themselves, so they are written with a
+// stack rather than by recursion. A reference back to an array still being written is
+// written as `[...]`.
+fn write_array(f: &mut fmt::Formatter<'_>, array: &Rc<RefCell<Array>>) -> fmt::Result {
+    let mut stack = vec![(Rc::clone(array), 0)];
+    let mut open = HashSet::from([Rc::as_ptr(array)]);
+    write!(f, "[")?;
+    while let Some((array, index)) = stack.last_mut() {
+        let element = array.borrow().get(*index).cloned();
+        let Some(element) = element else {
+            open.remove(&Rc::as_ptr(array));
+            stack.pop();
+            write!(f, "]")?;
+            continue;
+        };
+        if *index > 0 {
+            write!(f, ", ")?;
+        }
+        *index += 1;
+
+        match element {
+            Object::Array(inner) if open.contains(&Rc::as_ptr(&inner)) => write!(f, "[...]")?,
+            Object::Array(inner) => {
+                write!(f, "[")?;
+                open.insert(Rc::as_ptr(&inner));
+                stack.push((inner, 0));
+            }
+            element => write!(f, "{}", element)?,
+        }
+    }
+    Ok(())
+}
+
+/// The elements of an array.
+#[derive(Debug, Default)]
+pub struct Array(Vec<Object>);
+
+impl From<Vec<Object>> for Array {
+    fn from(elements: Vec<Object>) -> Self {
+        Array(elements)
+    }
+}
+
+impl Deref for Array {
+    type Target = Vec<Object>;
+
+    fn deref(&self) -> &Vec<Object> {
+        &self.0
+    }
+}
+
+impl DerefMut for Array {
+    fn deref_mut(&mut self) -> &mut Vec<Object> {
+        &mut self.0
+    }
+}
+
+impl Drop for Array {
+    fn drop(&mut self) {
+        let mut teardown = Teardown::default();
+        teardown.values.append(&mut self.0);
+        teardown.run();
+    }
+}
+
+/// Drops values without recursing into the arrays and environments only they refer to.
+/// Those can nest deeper than the native stack allows, like an array built up a million
+/// levels deep or a chain of closures each capturing the last, so they are emptied onto the
+/// worklist instead and then dropped with nothing left in them.
+#[derive(Default)]
+pub struct Teardown {
+    pub values: Vec<Object>,
+    pub environments: Vec<Rc<RefCell<Environment>>>,
+}
+
+impl Teardown {
+    pub fn run(mut self) {
+        loop {
+            if let Some(value) = self.values.pop() {
+                match value {
+                    Object::Array(array) => {
+                        if let Some(array) = Rc::into_inner(array) {
+                            self.values.append(&mut array.into_inner().0);
+                        }
+                    }
+                    Object::Function(function) => self.environments.push(function.env),
+                    Object::ReturnValue(value) => self.values.push(*value),
+                    Object::TailCall(call) => {
+                        let call = *call;
+                        self.values.push(call.function);
+                        self.values.extend(call.arguments);
+                    }
+                    _ => {}
+                }
+            } else if let Some(env) = self.environments.pop() {
+                if let Some(env) = Rc::into_inner(env) {
+                    env.into_inner().empty_into(&mut self);
+                }
+            } else {
+                return;
+            }
+        }
+    }
+}
+
+/// Functions every program can call without declaring them. A declaration of the same
+/// name hides one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `gc()` collects garbage now and returns how many objects it freed.
+    Gc,
+}
+
+impl Builtin {
+    pub fn lookup(name: Symbol) -> Option<Builtin> {
+        match &*name.as_str() {
+            "gc" => Some(Builtin::Gc),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Gc => "gc",
+        }
+    }
+
+    /// How many arguments it takes.
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Gc => 0,
+        }
+    }
+}
+
+/// A call whose operands have been evaluated but which hasn't been made yet.
+#[derive(Debug, Clone)]
+pub struct TailCall {
+    /// The `CallExpression` making the call.
+    pub call: ExpressionId,
+    pub ast: Rc<Ast>,
+    pub function: Object,
+    pub arguments: Vec<Object>,
+}
+
+#[derive(Clone)]
+pub struct Function {
+    pub parameters: Vec<Identifier>,
+    pub body: BlockId,
+    /// The arena `body` lives in, kept alive for as long as the function is.
+    pub ast: Rc<Ast>,
+    pub env: Rc<RefCell<Environment>>,
+}
+
+// The captured environment usually refers back to the function itself, so it is left out.
+impl Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("parameters", &self.parameters)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self
+            .parameters
+            .iter()
+            .map(|p| p.string(&self.ast))
+            .collect();
+        let body = self.ast[self.body].string(&self.ast);
+        write!(f, "fn({}) {{ {} }}", params.join(", "), body)
+    }
+}
@@ -0,0 +1,459 @@
+use crate::ast::*;
+use crate::evaluator::{is_truthy, Evaluator};
+use crate::object::Object;
+use crate::token::{Span, Token, TokenKind};
+use num_bigint::BigInt;
+use std::rc::Rc;
+
+/// Folds constant prefix and infix expressions, simplifies `x * 1` and `x + 0`, and prunes
+/// `if` branches whose condition is a constant.
+///
+/// Constants are computed by `evaluator`, so they follow its integer mode, and anything that
+/// would fail at run time (division by zero, overflow in checked mode, type mismatches) is left
+/// in place to fail there. Only a program that hasn't run yet can be rewritten; once function
+/// values share its arena it is left as it is.
+pub fn optimize(program: &mut Program, evaluator: &Evaluator) {
+    let Some(ast) = Rc::get_mut(&mut program.ast) else {
+        return;
+    };
+    let mut folder = Folder { ast, evaluator };
+    for statement in &program.statements {
+        folder.fold_statement(*statement);
+    }
+}
+
+enum Rewrite {
+    /// Replace the expression with a literal for the value.
+    Constant(Object, Span),
+    /// Replace the expression with one of its operands.
+    Operand(ExpressionId),
+    /// Replace the expression with an `if` that always takes `consequence`.
+    Branch(Token<'static>, BlockId),
+}
+
+struct Folder<'a> {
+    ast: &'a mut Ast,
+    evaluator: &'a Evaluator,
+}
+
+impl Folder<'_> {
+    fn fold_statement(&mut self, id: StatementId) {
+        let (expression, body) = match self.ast.statement(id) {
+            StatementNode::LetStatement(statement) => (statement.value, None),
+            StatementNode::ReturnStatement(statement) => (statement.return_value, None),
+            StatementNode::ExpressionStatement(statement) => (statement.expression, None),
+            StatementNode::WhileStatement(statement) => (statement.condition, Some(statement.body)),
+            StatementNode::ForStatement(statement) => (statement.iterable, Some(statement.body)),
+            StatementNode::BreakStatement(_) | StatementNode::ContinueStatement(_) => return,
+        };
+
+        self.fold_expression(expression);
+        if let Some(body) = body {
+            self.fold_block(body);
+        }
+    }
+
+    fn fold_block(&mut self, id: BlockId) {
+        for statement in self.ast[id].statements.clone() {
+            self.fold_statement(statement);
+        }
+    }
+
+    // Children are folded first, so a node only has to look at its immediate operands.
+    fn fold_expression(&mut self, id: ExpressionId) {
+        let (expressions, blocks) = self.children(id);
+        for expression in expressions {
+            self.fold_expression(expression);
+        }
+        for block in blocks {
+            self.fold_block(block);
+        }
+
+        match self.rewrite(id) {
+            Some(Rewrite::Constant(value, span)) => {
+                if let Some(literal) = literal(value, span) {
+                    self.ast.replace_expression(id, literal);
+                }
+            }
+            Some(Rewrite::Operand(operand)) => self.ast.copy_expression(id, operand),
+            Some(Rewrite::Branch(token, consequence)) => {
+                let condition = literal(Object::Boolean(true), token.span)
+                    .map(|condition| self.ast.add_expression(condition))
+                    .expect("booleans always have a literal");
+                let branch = IfExpression {
+                    token,
+                    condition,
+                    consequence,
+                    alternative: None,
+                };
+                self.ast.replace_expression(id, branch);
+            }
+            None => {}
+        }
+    }
+
+    fn children(&self, id: ExpressionId) -> (Vec<ExpressionId>, Vec<BlockId>) {
+        match self.ast.expression(id) {
+            ExpressionNode::Identifier(_)
+            | ExpressionNode::IntegerLiteral(_)
+            | ExpressionNode::StringLiteral(_)
+            | ExpressionNode::Boolean(_)
+            | ExpressionNode::NullLiteral(_) => (vec![], vec![]),
+            ExpressionNode::PrefixExpression(prefix) => (vec![prefix.right], vec![]),
+            ExpressionNode::InfixExpression(infix) => (vec![infix.left, infix.right], vec![]),
+            ExpressionNode::IfExpression(if_expression) => match if_expression.alternative {
+                Some(ElseBranch::Block(alternative)) => (
+                    vec![if_expression.condition],
+                    vec![if_expression.consequence, alternative],
+                ),
+                Some(ElseBranch::If(alternative)) => (
+                    vec![if_expression.condition, alternative],
+                    vec![if_expression.consequence],
+                ),
+                None => (
+                    vec![if_expression.condition],
+                    vec![if_expression.consequence],
+                ),
+            },
+            ExpressionNode::FunctionLiteral(function) => (vec![], vec![function.body]),
+            ExpressionNode::CallExpression(call) => {
+                let mut expressions = vec![call.function];
+                expressions.extend(&call.arguments);
+                (expressions, vec![])
+            }
+            ExpressionNode::ArrayLiteral(array) => (array.elements.clone(), vec![]),
+            ExpressionNode::IndexExpression(index) => (vec![index.left, index.index], vec![]),
+            ExpressionNode::AssignExpression(assign) => (vec![assign.target, assign.value], vec![]),
+        }
+    }
+
+    fn rewrite(&self, id: ExpressionId) -> Option<Rewrite> {
+        match self.ast.expression(id) {
+            ExpressionNode::PrefixExpression(prefix) => {
+                let right = self.constant(prefix.right)?;
+                let value =
+                    self.evaluator
+                        .eval_prefix_expression(&prefix.token, &prefix.operator, right);
+                (!value.is_error()).then_some(Rewrite::Constant(value, prefix.token.span))
+            }
+            ExpressionNode::InfixExpression(infix) => self.rewrite_infix(infix),
+            ExpressionNode::IfExpression(if_expression) => {
+                let condition = self.constant(if_expression.condition)?;
+                if is_truthy(&condition) {
+                    // Without an alternative there's nothing left to prune.
+                    if_expression.alternative.as_ref()?;
+                    Some(Rewrite::Branch(
+                        if_expression.token.clone(),
+                        if_expression.consequence,
+                    ))
+                } else {
+                    match if_expression.alternative {
+                        Some(ElseBranch::Block(alternative)) => {
+                            Some(Rewrite::Branch(if_expression.token.clone(), alternative))
+                        }
+                        Some(ElseBranch::If(alternative)) => Some(Rewrite::Operand(alternative)),
+                        None => Some(Rewrite::Constant(Object::Null, if_expression.token.span)),
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn rewrite_infix(&self, infix: &InfixExpression) -> Option<Rewrite> {
+        let span = infix.token.span;
+        let left = self.constant(infix.left);
+
+        match infix.operator.as_str() {
+            // The right operand only matters when the left one doesn't decide the result.
+            "&&" | "||" => {
+                let left = is_truthy(&left?);
+                if (infix.operator == "&&") != left {
+                    return Some(Rewrite::Constant(Object::Boolean(left), span));
+                }
+                let right = is_truthy(&self.constant(infix.right)?);
+                Some(Rewrite::Constant(Object::Boolean(right), span))
+            }
+            "??" => match left? {
+                Object::Null => Some(Rewrite::Operand(infix.right)),
+                left => Some(Rewrite::Constant(left, span)),
+            },
+            operator => match (left, self.constant(infix.right)) {
+                (Some(left), Some(right)) => {
+                    let value =
+                        self.evaluator
+                            .eval_infix_expression(&infix.token, operator, left, right);
+                    (!value.is_error()).then_some(Rewrite::Constant(value, span))
+                }
+                _ => self.simplify_identity(infix),
+            },
+        }
+    }
+
+    // `x * 1` and `x + 0` can only drop the constant when `x` is known to be an integer,
+    // since `s + 0` must still report a type mismatch for a string `s`.
+    fn simplify_identity(&self, infix: &InfixExpression) -> Option<Rewrite> {
+        let identity = match infix.operator.as_str() {
+            "*" => 1,
+            "+" => 0,
+            _ => return None,
+        };
+        let is_identity =
+            |id| matches!(self.constant(id), Some(Object::Integer(value)) if value == identity);
+
+        if is_identity(infix.right) && self.is_integer_valued(infix.left) {
+            Some(Rewrite::Operand(infix.left))
+        } else if is_identity(infix.left) && self.is_integer_valued(infix.right) {
+            Some(Rewrite::Operand(infix.right))
+        } else {
+            None
+        }
+    }
+
+    // Whether the expression either evaluates to an integer or fails, whatever its operands are.
+    fn is_integer_valued(&self, id: ExpressionId) -> bool {
+        match self.ast.expression(id) {
+            ExpressionNode::IntegerLiteral(_) => true,
+            ExpressionNode::PrefixExpression(prefix) => {
+                prefix.operator == "-" || prefix.operator == "~"
+            }
+            ExpressionNode::InfixExpression(infix) => match infix.operator.as_str() {
+                "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" => true,
+                "+" => self.is_integer_valued(infix.left) || self.is_integer_valued(infix.right),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // The value of a literal, unless evaluating it would fail.
+    fn constant(&self, id: ExpressionId) -> Option<Object> {
+        match self.ast.expression(id) {
+            ExpressionNode::IntegerLiteral(integer) => {
+                let value = self.evaluator.eval_integer_literal(integer);
+                (!value.is_error()).then_some(value)
+            }
+            ExpressionNode::Boolean(boolean) => Some(Object::Boolean(boolean.value)),
+            ExpressionNode::StringLiteral(string) => Some(Object::String(string.value.clone())),
+            ExpressionNode::NullLiteral(_) => Some(Object::Null),
+            _ => None,
+        }
+    }
+}
+
+// Folded literals take the span of the expression they replace.
+fn literal(value: Object, span: Span) -> Option<ExpressionNode> {
+    let node = match value {
+        Object::Integer(value) => IntegerLiteral {
+            token: Token::with_span(TokenKind::INTEGER, value.to_string(), span),
+            value: BigInt::from(value),
+        }
+        .into(),
+        Object::BigInteger(value) => IntegerLiteral {
+            token: Token::with_span(TokenKind::INTEGER, value.to_string(), span),
+            value,
+        }
+        .into(),
+        Object::Boolean(value) => {
+            let kind = if value {
+                TokenKind::TRUE
+            } else {
+                TokenKind::FALSE
+            };
+            Boolean {
+                token: Token::with_span(kind, value.to_string(), span),
+                value,
+            }
+            .into()
+        }
+        Object::String(value) => StringLiteral {
+            token: Token::with_span(TokenKind::STRING, escape(&value), span),
+            value,
+        }
+        .into(),
+        Object::Null => NullLiteral {
+            token: Token::with_span(TokenKind::NULL, "null", span),
+        }
+        .into(),
+        _ => return None,
+    };
+    Some(node)
+}
+
+// String tokens hold the source spelling, so folded strings are escaped back.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::evaluator::IntegerMode;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use std::cell::RefCell;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        program
+    }
+
+    fn optimized(input: &str, integer_mode: IntegerMode) -> Program {
+        let mut program = parse(input);
+        optimize(&mut program, &Evaluator::new(integer_mode));
+        program
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let tests = vec![
+            ("(5 + 5) * 2", "20"),
+            ("!(true == true)", "false"),
+            ("-(5 + 5)", "-10"),
+            ("~0 << 4", "-16"),
+            ("1 < 2 == true", "true"),
+            ("null == null", "true"),
+            ("\"a\" + \"b\\n\"", "\"ab\\n\""),
+            ("x + 2 * 3", "(x + 6)"),
+            ("let y = 2 * 3;", "let y = 6;"),
+            ("fn(x) { x + (2 * 3) }", "fn(x)(x + 6)"),
+            ("f(1 + 1)[2 - 1]", "(f(2)[1])"),
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("false && f()", "false"),
+            ("true || f()", "true"),
+            ("true && false", "false"),
+            ("true && f()", "(true && f())"),
+            ("null ?? x", "x"),
+            ("1 ?? x", "1"),
+        ];
+
+        for (input, expected) in tests {
+            let program = optimized(input, IntegerMode::Arbitrary);
+            assert_eq!(program.string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_identity_simplification() {
+        let tests = vec![
+            ("(x - 1) * 1", "(x - 1)"),
+            ("1 * (x * y)", "(x * y)"),
+            ("0 + -x", "(-x)"),
+            ("(x % 2) + 0 + 0", "(x % 2)"),
+            ("(1 + x) * 1", "(1 + x)"),
+            // Without knowing its type, `x` could be a string or a boolean.
+            ("x * 1", "(x * 1)"),
+            ("x + 0", "(x + 0)"),
+            ("\"s\" + 0", "(\"s\" + 0)"),
+            ("(a + b) * 1", "((a + b) * 1)"),
+        ];
+
+        for (input, expected) in tests {
+            let program = optimized(input, IntegerMode::Arbitrary);
+            assert_eq!(program.string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_if_pruning() {
+        let tests = vec![
+            ("if (1 < 2) { a } else { b }", "if (true) { a }"),
+            ("if (1 > 2) { a } else { b }", "if (true) { b }"),
+            (
+                "if (null) { a } else if (x) { b } else { c }",
+                "if (x) { b } else { c }",
+            ),
+            (
+                "if (false) { a } else if (1 == 2) { b } else { c }",
+                "if (true) { c }",
+            ),
+            ("if (false) { a }", "null"),
+            ("if (x) { 1 + 1 } else { 2 * 2 }", "if (x) { 2 } else { 4 }"),
+        ];
+
+        for (input, expected) in tests {
+            let program = optimized(input, IntegerMode::Arbitrary);
+            assert_eq!(program.string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_runtime_errors_are_kept() {
+        let tests = vec![
+            ("1 / 0", IntegerMode::Arbitrary, "(1 / 0)"),
+            ("(2 + 2) % (1 - 1)", IntegerMode::Arbitrary, "(4 % 0)"),
+            ("1 + true", IntegerMode::Arbitrary, "(1 + true)"),
+            ("-\"a\"", IntegerMode::Arbitrary, "(-\"a\")"),
+            ("1 << -1", IntegerMode::Arbitrary, "(1 << -1)"),
+            (
+                "9223372036854775807 + 1",
+                IntegerMode::Checked,
+                "(9223372036854775807 + 1)",
+            ),
+            (
+                "9223372036854775807 + 1",
+                IntegerMode::Wrapping,
+                "-9223372036854775808",
+            ),
+            (
+                "99999999999999999999 * 1",
+                IntegerMode::Checked,
+                "99999999999999999999",
+            ),
+        ];
+
+        for (input, integer_mode, expected) in tests {
+            let program = optimized(input, integer_mode);
+            assert_eq!(program.string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_optimized_programs_evaluate_the_same() {
+        let inputs = vec![
+            "let x = 4; (x - 1) * 1 + 0 * 2",
+            "let s = \"s\"; s + 0",
+            "let x = 7; if (1 + 1 == 2) { x * (3 - 2) } else { x / 0 }",
+            "let f = fn(n) { if (false) { 1 } else if (n > 1) { n * 1 } }; f(5)",
+            "(1 + 1) / (2 - 2)",
+            "9223372036854775807 + (0 + 1)",
+            "-(-9223372036854775807 - 1)",
+            "\"a\" + \"b\" == \"ab\"",
+            "let xs = [1, 2]; xs[2 - 1] = 10 * 1; xs",
+            "let n = null; n ?? (1 + 2)",
+            "undefined * 1",
+        ];
+
+        for integer_mode in [
+            IntegerMode::Arbitrary,
+            IntegerMode::Checked,
+            IntegerMode::Wrapping,
+        ] {
+            let evaluator = Evaluator::new(integer_mode);
+            for input in &inputs {
+                let plain = parse(input);
+                let optimized = optimized(input, integer_mode);
+
+                let env = Rc::new(RefCell::new(Environment::new()));
+                let expected = evaluator.eval_program(&plain, &env).to_string();
+                let env = Rc::new(RefCell::new(Environment::new()));
+                let actual = evaluator.eval_program(&optimized, &env).to_string();
+                assert_eq!(actual, expected, "{} in {:?}", input, integer_mode);
+            }
+        }
+    }
+}
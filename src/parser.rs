@@ -1,67 +1,215 @@
 use crate::ast::*;
-use crate::token::{Precedence, Token, TokenKind};
+use crate::lexer::Lexer;
+use crate::symbol::Symbol;
+use crate::token::{Precedence, Span, Token, TokenKind};
+use num_bigint::BigInt;
 use std::fmt::{self, Display};
 use std::iter::Peekable;
-use std::vec::IntoIter;
+use std::rc::Rc;
+
+// How deeply expressions and blocks may nest. Parsing recurses once per level, so without a limit
+// input like thousands of `(` would overflow the stack.
+const MAX_NESTING: usize = 256;
 
 #[derive(Debug)]
 pub enum ParseError {
-    UnexpectedToken(Token),
+    UnexpectedToken(Token<'static>),
+    ExpectedToken {
+        expected: TokenKind,
+        found: Token<'static>,
+    },
+    InvalidIntegerLiteral {
+        token: Token<'static>,
+        reason: String,
+    },
+    InvalidAssignmentTarget(Token<'static>),
+    OutsideLoop(Token<'static>),
+    InvalidEscape(Token<'static>),
+    NestedTooDeeply(Token<'static>),
 }
 
-impl Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken(token)
+            | ParseError::ExpectedToken { found: token, .. }
+            | ParseError::InvalidIntegerLiteral { token, .. }
+            | ParseError::InvalidAssignmentTarget(token)
+            | ParseError::OutsideLoop(token)
+            | ParseError::InvalidEscape(token)
+            | ParseError::NestedTooDeeply(token) => token.span,
+        }
+    }
+
+    pub fn token_mut(&mut self) -> &mut Token<'static> {
         match self {
-            ParseError::UnexpectedToken(token) => write!(f, "Unexpected token: {}", token),
+            ParseError::UnexpectedToken(token)
+            | ParseError::ExpectedToken { found: token, .. }
+            | ParseError::InvalidIntegerLiteral { token, .. }
+            | ParseError::InvalidAssignmentTarget(token)
+            | ParseError::OutsideLoop(token)
+            | ParseError::InvalidEscape(token)
+            | ParseError::NestedTooDeeply(token) => token,
+        }
+    }
+
+    /// The error without its position.
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::UnexpectedToken(token) => format!("Unexpected token: {}", token),
+            ParseError::ExpectedToken { expected, found } => format!(
+                "expected next token to be {}, got {} instead",
+                expected, found.kind
+            ),
+            ParseError::InvalidIntegerLiteral { token, reason } => {
+                format!("invalid integer literal {}: {}", token.literal, reason)
+            }
+            ParseError::InvalidAssignmentTarget(token) => {
+                format!("invalid assignment target for {}", token.literal)
+            }
+            ParseError::OutsideLoop(token) => format!("{} outside of a loop", token.literal),
+            ParseError::InvalidEscape(token) => format!(
+                "invalid escape sequence in string literal \"{}\"",
+                token.literal
+            ),
+            ParseError::NestedTooDeeply(_) => format!("nested more than {} deep", MAX_NESTING),
         }
     }
 }
 
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span().start, self.message())
+    }
+}
+
+/// A top-level statement and the source it was parsed from. A statement that failed to parse
+/// leaves only its errors, spanning the tokens skipped over.
+#[derive(Debug)]
+pub struct TopLevel {
+    pub statement: Option<StatementId>,
+    pub span: Span,
+    pub errors: Vec<ParseError>,
+}
+
 #[derive(Debug)]
-pub struct Parser {
-    pub tokens: Peekable<IntoIter<Token>>,
-    pub current_token: Token,
+pub struct Parser<'a> {
+    pub tokens: Peekable<Lexer<'a>>,
+    pub current_token: Token<'a>,
+    pub errors: Vec<ParseError>,
+    ast: Ast,
+    // Loops enclosing the current token within the innermost function.
+    loop_depth: usize,
+    // Expressions and blocks being parsed that enclose the current token.
+    nesting: usize,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Self {
         Parser {
-            tokens: tokens.into_iter().peekable(),
-            current_token: Token::new(TokenKind::EOF, String::from("")),
+            tokens: lexer.peekable(),
+            current_token: Token::new(TokenKind::EOF, ""),
+            errors: Vec::new(),
+            ast: Ast::default(),
+            loop_depth: 0,
+            nesting: 0,
         }
     }
 
-    fn next(&mut self) -> Option<Token> {
+    fn next(&mut self) -> Option<&Token<'a>> {
         self.current_token = self.tokens.next()?;
-        Some(self.current_token.clone())
+        Some(&self.current_token)
     }
 
-    fn peek(&mut self) -> Option<&Token> {
+    fn peek(&mut self) -> Option<&Token<'a>> {
         self.tokens.peek()
     }
 
+    // AST nodes outlive the source text, so the tokens they keep are copied out of it.
+    fn owned_token(&self) -> Token<'static> {
+        self.current_token.clone().into_owned()
+    }
+
+    // The lexer interns every identifier, so the fallback only covers hand-built tokens.
+    fn current_identifier(&self) -> Identifier {
+        let token = self.owned_token();
+        let value = token
+            .symbol
+            .unwrap_or_else(|| Symbol::intern(&token.literal));
+        Identifier {
+            token,
+            value,
+            binding: None,
+        }
+    }
+
+    /// Parses into an existing arena, so a program can be reparsed a piece at a time.
+    pub fn resume(lexer: Lexer<'a>, ast: Ast) -> Self {
+        Parser {
+            ast,
+            ..Parser::new(lexer)
+        }
+    }
+
+    /// Hands back the arena, with every node parsed so far.
+    pub fn into_ast(self) -> Ast {
+        self.ast
+    }
+
     pub fn parse(&mut self) -> Program {
         self.parse_program()
     }
 
     fn parse_program(&mut self) -> Program {
-        let mut program = Program {
-            statements: Vec::new(),
-        };
+        let mut statements = Vec::new();
 
-        while let Some(_) = self.peek() {
-            if let Some(statement) = self.parse_statement() {
-                program.statements.push(statement);
-            }
+        while let Some(top_level) = self.parse_top_level() {
+            statements.extend(top_level.statement);
+            self.errors.extend(top_level.errors);
+        }
+        Program {
+            ast: Rc::new(std::mem::take(&mut self.ast)),
+            statements,
+        }
+    }
+
+    /// Parses the next top-level statement, or skips past one that doesn't parse.
+    /// Returns `None` once the input is used up.
+    pub fn parse_top_level(&mut self) -> Option<TopLevel> {
+        let start = self.peek()?.span.start;
+        let errors = self.errors.len();
+
+        let statement = self.parse_statement();
+        if statement.is_none() {
+            self.synchronize();
         }
-        program
+        Some(TopLevel {
+            statement,
+            span: Span {
+                start,
+                end: self.current_token.span.end,
+            },
+            errors: self.errors.split_off(errors),
+        })
+    }
+
+    // Skips the rest of a statement that failed to parse so one mistake is reported once.
+    // Stops before a closing brace so the enclosing block can still be finished.
+    fn synchronize(&mut self) {
+        while !self.current_token_is(TokenKind::SEMICOLON)
+            && !self.peek_token_is(TokenKind::RBRACE)
+            && self.next().is_some()
+        {}
     }
 
-    fn parse_statement(&mut self) -> Option<Box<dyn Statement>> {
+    fn parse_statement(&mut self) -> Option<StatementId> {
         match self.peek() {
             Some(token) => match token.kind {
                 TokenKind::LET => self.parse_let_statement(),
                 TokenKind::RETURN => self.parse_return_statement(),
+                TokenKind::WHILE => self.parse_while_statement(),
+                TokenKind::FOR => self.parse_for_statement(),
+                TokenKind::BREAK | TokenKind::CONTINUE => self.parse_loop_control_statement(),
                 _ => self.parse_expression_statement(),
             },
             None => None,
@@ -69,107 +217,232 @@ impl Parser {
     }
 
     // let <identifier> = <expression>;
-    fn parse_let_statement(&mut self) -> Option<Box<dyn Statement>> {
-        let let_token = self.next()?;
+    fn parse_let_statement(&mut self) -> Option<StatementId> {
+        self.next()?;
+        let let_token = self.owned_token();
 
         if !self.expect_peek(TokenKind::IDENTIFIER) {
             return None;
         }
 
-        let name = Identifier {
-            token: self.current_token.clone(),
-            value: self.current_token.literal.clone(),
-        };
+        let name = self.current_identifier();
 
         if !self.expect_peek(TokenKind::ASSIGN) {
             return None;
         }
 
-        // parse_expression
-        let dummy_expression = Box::new(Identifier {
-            token: let_token.clone(),
-            value: String::from("dummy"),
-        });
+        self.next();
+        let value = self.parse_expression(Precedence::Lowest)?;
 
-        while !self.current_token_is(TokenKind::SEMICOLON) {
+        if self.peek_token_is(TokenKind::SEMICOLON) {
             self.next();
         }
 
-        Some(Box::new(LetStatement {
+        Some(self.ast.add_statement(LetStatement {
             token: let_token,
             name,
-            value: dummy_expression,
+            value,
         }))
     }
 
     // return <expression>;
-    fn parse_return_statement(&mut self) -> Option<Box<dyn Statement>> {
-        let return_token = self.next()?;
+    fn parse_return_statement(&mut self) -> Option<StatementId> {
+        self.next()?;
+        let return_token = self.owned_token();
 
-        // TODO: Expressionを実装後に書き換える
         self.next();
+        let return_value = self.parse_expression(Precedence::Lowest)?;
 
-        while !self.current_token_is(TokenKind::SEMICOLON) {
+        if self.peek_token_is(TokenKind::SEMICOLON) {
             self.next();
         }
 
-        // TODO: Expressionを実装後に書き換える
-        let dummy_expression = Box::new(Identifier {
-            token: return_token.clone(),
-            value: String::from("dummy"),
-        });
+        Some(self.ast.add_statement(ReturnStatement {
+            token: return_token,
+            return_value,
+        }))
+    }
+
+    // while (<condition>) { <body> }
+    fn parse_while_statement(&mut self) -> Option<StatementId> {
+        self.next()?;
+        let while_token = self.owned_token();
+
+        if !self.expect_peek(TokenKind::LPAREN) {
+            return None;
+        }
+
+        self.next();
+        let condition = self.parse_expression(Precedence::Lowest)?;
 
-        Some(Box::new(ReturnStatement {
-            token: return_token.clone(),
-            return_value: dummy_expression,
+        if !self.expect_peek(TokenKind::RPAREN) {
+            return None;
+        }
+
+        let body = self.parse_loop_body()?;
+
+        Some(self.ast.add_statement(WhileStatement {
+            token: while_token,
+            condition,
+            body,
         }))
     }
 
-    fn parse_expression_statement(&mut self) -> Option<Box<dyn Statement>> {
-        let exp_token = self.next()?;
+    // for (<identifier> in <expression>) { <body> }
+    fn parse_for_statement(&mut self) -> Option<StatementId> {
+        self.next()?;
+        let for_token = self.owned_token();
+
+        if !self.expect_peek(TokenKind::LPAREN) {
+            return None;
+        }
+
+        if !self.expect_peek(TokenKind::IDENTIFIER) {
+            return None;
+        }
+
+        let variable = self.current_identifier();
+
+        if !self.expect_peek(TokenKind::IN) {
+            return None;
+        }
+
+        self.next();
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenKind::RPAREN) {
+            return None;
+        }
+
+        let body = self.parse_loop_body()?;
+
+        Some(self.ast.add_statement(ForStatement {
+            token: for_token,
+            variable,
+            iterable,
+            body,
+        }))
+    }
+
+    fn parse_loop_body(&mut self) -> Option<BlockId> {
+        if !self.expect_peek(TokenKind::LBRACE) {
+            return None;
+        }
+
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+
+        if self.peek_token_is(TokenKind::SEMICOLON) {
+            self.next();
+        }
+
+        body
+    }
+
+    // break; or continue;
+    fn parse_loop_control_statement(&mut self) -> Option<StatementId> {
+        self.next()?;
+        let token = self.owned_token();
+
+        if self.loop_depth == 0 {
+            self.errors.push(ParseError::OutsideLoop(token));
+            return None;
+        }
+
+        if self.peek_token_is(TokenKind::SEMICOLON) {
+            self.next();
+        }
+
+        if token.kind == TokenKind::BREAK {
+            Some(self.ast.add_statement(BreakStatement { token }))
+        } else {
+            Some(self.ast.add_statement(ContinueStatement { token }))
+        }
+    }
+
+    fn parse_expression_statement(&mut self) -> Option<StatementId> {
+        self.next()?;
+        let exp_token = self.owned_token();
 
-        let expression = self.parse_expression(Precedence::Lowest);
+        let expression = self.parse_expression(Precedence::Lowest)?;
 
         while self.peek_token_is(TokenKind::SEMICOLON) {
             self.next();
         }
 
-        return Some(Box::new(ExpressionStatement {
-            token: exp_token.clone(),
-            expression: expression.unwrap(),
-        }));
+        Some(self.ast.add_statement(ExpressionStatement {
+            token: exp_token,
+            expression,
+        }))
     }
 
-    fn parse_block_statement(&mut self) -> Option<BlockStatement> {
-        let block_token = self.current_token.clone();
+    fn parse_block_statement(&mut self) -> Option<BlockId> {
+        self.nested(Self::parse_block)
+    }
+
+    fn parse_block(&mut self) -> Option<BlockId> {
+        let block_token = self.owned_token();
         let mut block_statements = Vec::new();
 
-        while !self.current_token_is(TokenKind::RBRACE) && !self.current_token_is(TokenKind::EOF) {
-            let statement = self.parse_statement();
-            if let Some(statement) = statement {
-                block_statements.push(statement);
+        while !self.peek_token_is(TokenKind::RBRACE) {
+            if self.peek().is_none() {
+                self.expect_peek(TokenKind::RBRACE);
+                return None;
+            }
+            match self.parse_statement() {
+                Some(statement) => block_statements.push(statement),
+                None => self.synchronize(),
             }
-            self.next()?;
         }
+        self.next();
 
-        Some(BlockStatement {
+        Some(self.ast.add_block(BlockStatement {
             token: block_token,
             statements: block_statements,
-        })
+            end: self.current_token.span,
+        }))
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<ExpressionId> {
+        self.nested(|parser| parser.parse_operators(precedence))
+    }
+
+    // Every path the parser recurses along runs through here, so nesting is bounded however
+    // the source nests.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        if self.nesting == MAX_NESTING {
+            self.errors
+                .push(ParseError::NestedTooDeeply(self.owned_token()));
+            return None;
+        }
+        self.nesting += 1;
+        let parsed = parse(self);
+        self.nesting -= 1;
+        parsed
     }
 
-    fn parse_expression(&mut self, precedence: Precedence) -> Option<Box<dyn Expression>> {
+    // Parses an operand and then every operator binding tighter than `precedence`.
+    fn parse_operators(&mut self, precedence: Precedence) -> Option<ExpressionId> {
         let mut left_exp = match self.current_token.kind {
             TokenKind::IDENTIFIER => self.parse_identifier(),
             TokenKind::INTEGER => self.parse_integer_literal(),
+            TokenKind::STRING => self.parse_string_literal(),
             TokenKind::BANG => self.parse_prefix_expression(),
             TokenKind::MINUS => self.parse_prefix_expression(),
+            TokenKind::TILDE => self.parse_prefix_expression(),
             TokenKind::TRUE | TokenKind::FALSE => self.parse_boolean(),
+            TokenKind::NULL => self.parse_null_literal(),
             TokenKind::LPAREN => self.parse_grouped_expression(),
             TokenKind::IF => self.parse_if_expression(),
             TokenKind::FUNCTION => self.parse_function_literal(),
-            _ => None,
-        };
+            TokenKind::LBRACKET => self.parse_array_literal(),
+            _ => {
+                self.errors
+                    .push(ParseError::UnexpectedToken(self.owned_token()));
+                None
+            }
+        }?;
 
         while !self.peek_token_is(TokenKind::SEMICOLON) && precedence < self.peek_precedence() {
             let token = self.peek()?;
@@ -179,74 +452,221 @@ impl Parser {
                 | TokenKind::MINUS
                 | TokenKind::SLASH
                 | TokenKind::ASTERISK
+                | TokenKind::PERCENT
                 | TokenKind::EQ
                 | TokenKind::NOT_EQ
                 | TokenKind::LT
-                | TokenKind::GT => {
+                | TokenKind::GT
+                | TokenKind::LT_EQ
+                | TokenKind::GT_EQ
+                | TokenKind::AND
+                | TokenKind::OR
+                | TokenKind::AMPERSAND
+                | TokenKind::PIPE
+                | TokenKind::CARET
+                | TokenKind::LSHIFT
+                | TokenKind::RSHIFT
+                | TokenKind::NULL_COALESCE => {
                     self.next();
-                    left_exp = self.parse_infix_expression(left_exp.unwrap());
+                    left_exp = self.parse_infix_expression(left_exp)?;
+                }
+                TokenKind::LPAREN => {
+                    self.next();
+                    left_exp = self.parse_call_expression(left_exp, false)?;
+                }
+                TokenKind::LBRACKET => {
+                    self.next();
+                    left_exp = self.parse_index_expression(left_exp, false)?;
+                }
+                TokenKind::OPTIONAL_CHAIN => {
+                    self.next();
+                    left_exp = self.parse_optional_chain(left_exp)?;
+                }
+                TokenKind::ASSIGN
+                | TokenKind::PLUS_ASSIGN
+                | TokenKind::MINUS_ASSIGN
+                | TokenKind::ASTERISK_ASSIGN
+                | TokenKind::SLASH_ASSIGN => {
+                    self.next();
+                    left_exp = self.parse_assign_expression(left_exp)?;
                 }
                 _ => break,
             };
         }
 
-        left_exp
+        Some(left_exp)
     }
 
-    fn parse_prefix_expression(&mut self) -> Option<Box<dyn Expression>> {
-        let prefix_token = self.current_token.clone();
+    fn parse_prefix_expression(&mut self) -> Option<ExpressionId> {
+        let prefix_token = self.owned_token();
 
         self.next();
 
-        let right_expression = self.parse_expression(Precedence::Prefix);
+        let right_expression = self.parse_expression(Precedence::Prefix)?;
 
-        Some(Box::new(PrefixExpression {
+        Some(self.ast.add_expression(PrefixExpression {
             token: prefix_token.clone(),
-            operator: prefix_token.literal,
-            right: right_expression.unwrap(),
+            operator: prefix_token.literal.to_string(),
+            right: right_expression,
         }))
     }
 
-    fn parse_infix_expression(
-        &mut self,
-        left_exp: Box<dyn Expression>,
-    ) -> Option<Box<dyn Expression>> {
-        let infix_token = self.current_token.clone();
+    fn parse_infix_expression(&mut self, left_exp: ExpressionId) -> Option<ExpressionId> {
+        let infix_token = self.owned_token();
         let precedence = self.current_precedence();
 
         self.next();
 
-        let right_expression = self.parse_expression(precedence);
+        let right_expression = self.parse_expression(precedence)?;
 
-        Some(Box::new(InfixExpression {
+        Some(self.ast.add_expression(InfixExpression {
             token: infix_token.clone(),
-            operator: infix_token.literal,
+            operator: infix_token.literal.to_string(),
             left: left_exp,
-            right: right_expression.unwrap(),
+            right: right_expression,
+        }))
+    }
+
+    fn parse_call_expression(
+        &mut self,
+        function: ExpressionId,
+        optional: bool,
+    ) -> Option<ExpressionId> {
+        let call_token = self.owned_token();
+        let arguments = self.parse_expression_list(TokenKind::RPAREN)?;
+
+        Some(self.ast.add_expression(CallExpression {
+            token: call_token,
+            function,
+            arguments,
+            optional,
+        }))
+    }
+
+    // <expression>?.[<index>] or <expression>?.(<arguments>)
+    fn parse_optional_chain(&mut self, left: ExpressionId) -> Option<ExpressionId> {
+        if self.peek_token_is(TokenKind::LBRACKET) {
+            self.next();
+            self.parse_index_expression(left, true)
+        } else if self.peek_token_is(TokenKind::LPAREN) {
+            self.next();
+            self.parse_call_expression(left, true)
+        } else {
+            let found = self.peek_or_eof();
+            self.errors.push(ParseError::UnexpectedToken(found));
+            None
+        }
+    }
+
+    fn parse_index_expression(
+        &mut self,
+        left: ExpressionId,
+        optional: bool,
+    ) -> Option<ExpressionId> {
+        let index_token = self.owned_token();
+
+        self.next();
+        let index = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenKind::RBRACKET) {
+            return None;
+        }
+
+        Some(self.ast.add_expression(IndexExpression {
+            token: index_token,
+            left,
+            index,
+            optional,
         }))
     }
 
-    fn parse_grouped_expression(&mut self) -> Option<Box<dyn Expression>> {
+    // Assignment is right-associative, so the value is parsed at the lowest precedence.
+    fn parse_assign_expression(&mut self, target: ExpressionId) -> Option<ExpressionId> {
+        let assign_token = self.owned_token();
+
+        let assignable = match self.ast.expression(target) {
+            ExpressionNode::Identifier(_) => true,
+            ExpressionNode::IndexExpression(index) => !index.optional,
+            _ => false,
+        };
+        if !assignable {
+            self.errors
+                .push(ParseError::InvalidAssignmentTarget(assign_token));
+            return None;
+        }
+
         self.next();
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        Some(self.ast.add_expression(AssignExpression {
+            token: assign_token.clone(),
+            target,
+            operator: assign_token.literal.to_string(),
+            value,
+        }))
+    }
+
+    fn parse_array_literal(&mut self) -> Option<ExpressionId> {
+        let array_token = self.owned_token();
+        let elements = self.parse_expression_list(TokenKind::RBRACKET)?;
 
-        let exp = self.parse_expression(Precedence::Lowest);
+        Some(self.ast.add_expression(ArrayLiteral {
+            token: array_token,
+            elements,
+        }))
+    }
+
+    fn parse_expression_list(&mut self, end: TokenKind) -> Option<Vec<ExpressionId>> {
+        let mut list = vec![];
+
+        if self.peek_token_is(end.clone()) {
+            self.next();
+            return Some(list);
+        }
+
+        self.next();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+
+        while self.peek_token_is(TokenKind::COMMA) {
+            self.next();
+            self.next();
+            list.push(self.parse_expression(Precedence::Lowest)?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+
+        Some(list)
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<ExpressionId> {
+        self.next();
+
+        let exp = self.parse_expression(Precedence::Lowest)?;
 
         if !self.expect_peek(TokenKind::RPAREN) {
             return None;
         }
 
-        exp
+        Some(exp)
+    }
+
+    fn parse_if_expression(&mut self) -> Option<ExpressionId> {
+        let if_expression = self.parse_if()?;
+        Some(self.ast.add_expression(if_expression))
     }
 
-    fn parse_if_expression(&mut self) -> Option<Box<dyn Expression>> {
-        let if_toknen = self.current_token.clone();
+    // if (<condition>) { <consequence> } [else if (...) { ... }]* [else { <alternative> }]
+    fn parse_if(&mut self) -> Option<IfExpression> {
+        let if_toknen = self.owned_token();
 
         if !self.expect_peek(TokenKind::LPAREN) {
             return None;
         }
 
         self.next();
-        let condition = self.parse_expression(Precedence::Lowest);
+        let condition = self.parse_expression(Precedence::Lowest)?;
 
         if !self.expect_peek(TokenKind::RPAREN) {
             return None;
@@ -256,44 +676,84 @@ impl Parser {
             return None;
         }
 
-        let consequence = self.parse_block_statement();
+        let consequence = self.parse_block_statement()?;
 
         let alternative = if self.peek_token_is(TokenKind::ELSE) {
             self.next();
 
-            if !self.expect_peek(TokenKind::LBRACE) {
-                return None;
+            if self.peek_token_is(TokenKind::IF) {
+                self.next();
+                Some(ElseBranch::If(self.nested(Self::parse_if_expression)?))
+            } else {
+                if !self.expect_peek(TokenKind::LBRACE) {
+                    return None;
+                }
+                Some(ElseBranch::Block(self.parse_block_statement()?))
             }
-
-            self.parse_block_statement()
         } else {
             None
         };
 
-        Some(Box::new(IfExpression {
+        Some(IfExpression {
             token: if_toknen,
-            condition: condition.unwrap(),
-            consequence: consequence.unwrap(),
+            condition,
+            consequence,
             alternative,
-        }))
+        })
     }
 
-    fn parse_identifier(&mut self) -> Option<Box<dyn Expression>> {
-        Some(Box::new(Identifier {
-            token: self.current_token.clone(),
-            value: self.current_token.literal.clone(),
-        }))
+    fn parse_identifier(&mut self) -> Option<ExpressionId> {
+        let identifier = self.current_identifier();
+        Some(self.ast.add_expression(identifier))
+    }
+
+    fn parse_integer_literal(&mut self) -> Option<ExpressionId> {
+        match parse_integer(&self.current_token.literal) {
+            Ok(value) => Some(self.ast.add_expression(IntegerLiteral {
+                token: self.owned_token(),
+                value,
+            })),
+            Err(reason) => {
+                self.errors.push(ParseError::InvalidIntegerLiteral {
+                    token: self.owned_token(),
+                    reason,
+                });
+                None
+            }
+        }
     }
 
-    fn parse_integer_literal(&mut self) -> Option<Box<dyn Expression>> {
-        Some(Box::new(IntegerLiteral {
-            token: self.current_token.clone(),
-            value: self.current_token.literal.parse().unwrap(),
+    fn parse_string_literal(&mut self) -> Option<ExpressionId> {
+        let mut value = String::new();
+        let mut chars = self.current_token.literal.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                _ => {
+                    self.errors
+                        .push(ParseError::InvalidEscape(self.owned_token()));
+                    return None;
+                }
+            }
+        }
+
+        Some(self.ast.add_expression(StringLiteral {
+            token: self.owned_token(),
+            value,
         }))
     }
 
-    fn parse_function_literal(&mut self) -> Option<Box<dyn Expression>> {
-        let function_token = self.current_token.clone();
+    fn parse_function_literal(&mut self) -> Option<ExpressionId> {
+        let function_token = self.owned_token();
 
         if !self.expect_peek(TokenKind::LPAREN) {
             return None;
@@ -305,12 +765,16 @@ impl Parser {
             return None;
         }
 
+        // A loop around the function literal can't be left from inside its body.
+        let loop_depth = std::mem::take(&mut self.loop_depth);
         let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
+        let body = body?;
 
-        Some(Box::new(FunctionLiteral {
+        Some(self.ast.add_expression(FunctionLiteral {
             token: function_token,
             parameters,
-            body: body.unwrap(),
+            body,
         }))
     }
 
@@ -324,10 +788,7 @@ impl Parser {
 
         self.next();
 
-        let identifier = Identifier {
-            token: self.current_token.clone(),
-            value: self.current_token.literal.clone(),
-        };
+        let identifier = self.current_identifier();
 
         identifiers.push(identifier);
 
@@ -335,10 +796,7 @@ impl Parser {
             self.next();
             self.next();
 
-            let identifier = Identifier {
-                token: self.current_token.clone(),
-                value: self.current_token.literal.clone(),
-            };
+            let identifier = self.current_identifier();
 
             identifiers.push(identifier);
         }
@@ -350,15 +808,22 @@ impl Parser {
         identifiers
     }
 
-    fn parse_boolean(&mut self) -> Option<Box<dyn Expression>> {
-        Some(Box::new(Boolean {
-            token: self.current_token.clone(),
+    fn parse_boolean(&mut self) -> Option<ExpressionId> {
+        let boolean = Boolean {
+            token: self.owned_token(),
             value: self.current_token_is(TokenKind::TRUE),
+        };
+        Some(self.ast.add_expression(boolean))
+    }
+
+    fn parse_null_literal(&mut self) -> Option<ExpressionId> {
+        Some(self.ast.add_expression(NullLiteral {
+            token: self.owned_token(),
         }))
     }
 
     fn peek_token_is(&mut self, kind: TokenKind) -> bool {
-        self.peek().map_or(false, |token| token.kind == kind)
+        self.peek().is_some_and(|token| token.kind == kind)
     }
 
     fn current_token_is(&mut self, kind: TokenKind) -> bool {
@@ -370,15 +835,24 @@ impl Parser {
             self.next();
             true
         } else {
-            eprintln!(
-                "expected next token to be {:?}, got {:?} instead",
-                kind,
-                self.peek()
-            );
+            let found = self.peek_or_eof();
+            self.errors.push(ParseError::ExpectedToken {
+                expected: kind,
+                found,
+            });
             false
         }
     }
 
+    // The lexer emits no EOF token, so one is synthesized just past the current token.
+    fn peek_or_eof(&mut self) -> Token<'static> {
+        let end = self.current_token.span.end;
+        match self.peek() {
+            Some(token) => token.clone().into_owned(),
+            None => Token::with_span(TokenKind::EOF, "", Span { start: end, end }),
+        }
+    }
+
     fn peek_precedence(&mut self) -> Precedence {
         match self.peek() {
             Some(token) => token.precedence(),
@@ -391,10 +865,54 @@ impl Parser {
     }
 }
 
+// Accepts decimal, 0x, 0o and 0b literals with single `_` separators between digits.
+fn parse_integer(literal: &str) -> Result<BigInt, String> {
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x" | "0X") => (16, &literal[2..]),
+        Some("0o" | "0O") => (8, &literal[2..]),
+        Some("0b" | "0B") => (2, &literal[2..]),
+        _ => (10, literal),
+    };
+
+    if let Some(c) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+        return Err(format!(
+            "invalid digit '{}' for a base {} literal",
+            c, radix
+        ));
+    }
+    if digits.chars().all(|c| c == '_') {
+        return Err(String::from("missing digits"));
+    }
+    if digits.starts_with('_') {
+        return Err(String::from("separator before the first digit"));
+    }
+    if digits.ends_with('_') {
+        return Err(String::from("separator after the last digit"));
+    }
+    if digits.contains("__") {
+        return Err(String::from("repeated separator"));
+    }
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+
+    BigInt::parse_bytes(digits.as_bytes(), radix).ok_or_else(|| String::from("malformed"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::lexer::tokenize;
+    use crate::lexer::Lexer;
+
+    fn statement_string(program: &Program, index: usize) -> String {
+        program.ast[program.statements[index]].string(&program.ast)
+    }
+
+    // The expression the first statement of `program` consists of.
+    fn first_expression(program: &Program) -> ExpressionId {
+        match program.ast.statement(program.statements[0]) {
+            StatementNode::ExpressionStatement(statement) => statement.expression,
+            statement => panic!("not an expression statement: {:?}", statement),
+        }
+    }
 
     #[test]
     fn test_parser() {
@@ -404,13 +922,13 @@ mod tests {
         let foobar = 838383;
                 "#;
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
 
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        for stmt in program.statements {
-            assert_eq!(stmt.token_literal(), "let");
+        for stmt in &program.statements {
+            assert_eq!(program.ast[*stmt].token_literal(), "let");
         }
     }
 
@@ -422,13 +940,13 @@ mod tests {
         return 993322;
                 "#;
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
 
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        for stmt in program.statements {
-            assert_eq!(stmt.token_literal(), "return");
+        for stmt in &program.statements {
+            assert_eq!(program.ast[*stmt].token_literal(), "return");
         }
     }
 
@@ -439,25 +957,84 @@ mod tests {
         fuga;
                 "#;
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
 
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        assert_eq!(program.statements[0].token_literal(), "hoge");
-        assert_eq!(program.statements[1].token_literal(), "fuga");
+        assert_eq!(program.ast[program.statements[0]].token_literal(), "hoge");
+        assert_eq!(program.ast[program.statements[1]].token_literal(), "fuga");
     }
 
     #[test]
     fn test_integer_literal_expression() {
         let input = "5;";
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
 
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        assert_eq!(program.statements[0].token_literal(), "5");
+        assert_eq!(program.ast[program.statements[0]].token_literal(), "5");
+    }
+
+    #[test]
+    fn test_radix_integer_literals() {
+        let tests = [
+            ("0xFF", "255"),
+            ("0Xff", "255"),
+            ("0b1010", "10"),
+            ("0o755", "493"),
+            ("1_000_000", "1000000"),
+            ("0xdead_beef", "3735928559"),
+            ("0b1111_0000", "240"),
+            ("007", "7"),
+            ("0xFFFF_FFFF_FFFF_FFFF_FFFF", "1208925819614629174706175"),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            assert_eq!(lexer.clone().count(), 1);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse();
+
+            assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+            let ExpressionNode::IntegerLiteral(integer) =
+                program.ast.expression(first_expression(&program))
+            else {
+                panic!("not an integer literal: {}", input);
+            };
+            assert_eq!(integer.value.to_string(), expected);
+            assert_eq!(integer.token.literal, input);
+            assert_eq!(statement_string(&program, 0), input);
+        }
+    }
+
+    #[test]
+    fn test_invalid_radix_integer_literals() {
+        let input = "0b102;\n0o8;\n0xFG;\n0x;\n0b__;\n12abc;\n0x_;\n0x_1;\n1_;\n1__0;\n0b1__0_;";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "1:1: invalid integer literal 0b102: invalid digit '2' for a base 2 literal",
+                "2:1: invalid integer literal 0o8: invalid digit '8' for a base 8 literal",
+                "3:1: invalid integer literal 0xFG: invalid digit 'G' for a base 16 literal",
+                "4:1: invalid integer literal 0x: missing digits",
+                "5:1: invalid integer literal 0b__: missing digits",
+                "6:1: invalid integer literal 12abc: invalid digit 'a' for a base 10 literal",
+                "7:1: invalid integer literal 0x_: missing digits",
+                "8:1: invalid integer literal 0x_1: separator before the first digit",
+                "9:1: invalid integer literal 1_: separator after the last digit",
+                "10:1: invalid integer literal 1__0: repeated separator",
+                "11:1: invalid integer literal 0b1__0_: separator after the last digit",
+            ]
+        );
     }
 
     #[test]
@@ -467,13 +1044,13 @@ mod tests {
     -15;
             "#;
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
 
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        assert_eq!(program.statements[0].string(), "(!5)");
-        assert_eq!(program.statements[1].string(), "(-15)");
+        assert_eq!(statement_string(&program, 0), "(!5)");
+        assert_eq!(statement_string(&program, 1), "(-15)");
     }
 
     #[test]
@@ -507,68 +1084,390 @@ false == false;
 -(5 + 5);
 !(true == true);
 "#;
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        assert_eq!(program.statements[0].string(), "(5 + 5)");
-        assert_eq!(program.statements[1].string(), "(5 - 5)");
-        assert_eq!(program.statements[2].string(), "(5 * 5)");
-        assert_eq!(program.statements[3].string(), "(5 / 5)");
-        assert_eq!(program.statements[4].string(), "(5 > 5)");
-        assert_eq!(program.statements[5].string(), "(5 < 5)");
-        assert_eq!(program.statements[6].string(), "(5 == 5)");
-        assert_eq!(program.statements[7].string(), "(5 != 5)");
-        assert_eq!(program.statements[8].string(), "((-a) * b)");
-        assert_eq!(program.statements[9].string(), "(!(-a))");
-        assert_eq!(program.statements[10].string(), "((a + b) + c)");
-        assert_eq!(program.statements[11].string(), "((a + b) - c)");
-        assert_eq!(program.statements[12].string(), "((a * b) * c)");
-        assert_eq!(program.statements[13].string(), "((a * b) / c)");
-        assert_eq!(program.statements[14].string(), "(a + (b / c))");
+        assert_eq!(statement_string(&program, 0), "(5 + 5)");
+        assert_eq!(statement_string(&program, 1), "(5 - 5)");
+        assert_eq!(statement_string(&program, 2), "(5 * 5)");
+        assert_eq!(statement_string(&program, 3), "(5 / 5)");
+        assert_eq!(statement_string(&program, 4), "(5 > 5)");
+        assert_eq!(statement_string(&program, 5), "(5 < 5)");
+        assert_eq!(statement_string(&program, 6), "(5 == 5)");
+        assert_eq!(statement_string(&program, 7), "(5 != 5)");
+        assert_eq!(statement_string(&program, 8), "((-a) * b)");
+        assert_eq!(statement_string(&program, 9), "(!(-a))");
+        assert_eq!(statement_string(&program, 10), "((a + b) + c)");
+        assert_eq!(statement_string(&program, 11), "((a + b) - c)");
+        assert_eq!(statement_string(&program, 12), "((a * b) * c)");
+        assert_eq!(statement_string(&program, 13), "((a * b) / c)");
+        assert_eq!(statement_string(&program, 14), "(a + (b / c))");
         assert_eq!(
-            program.statements[15].string(),
+            statement_string(&program, 15),
             "(((a + (b * c)) + (d / e)) - f)"
         );
-        assert_eq!(program.statements[16].string(), "(3 + 4)");
-        assert_eq!(program.statements[17].string(), "((-5) * 5)");
-        assert_eq!(program.statements[18].string(), "((5 > 4) == (3 < 4))");
+        assert_eq!(statement_string(&program, 16), "(3 + 4)");
+        assert_eq!(statement_string(&program, 17), "((-5) * 5)");
+        assert_eq!(statement_string(&program, 18), "((5 > 4) == (3 < 4))");
         assert_eq!(
-            program.statements[19].string(),
+            statement_string(&program, 19),
             "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))"
         );
-        assert_eq!(program.statements[20].string(), "(true == true)");
-        assert_eq!(program.statements[21].string(), "(true != false)");
-        assert_eq!(program.statements[22].string(), "(false == false)");
-        assert_eq!(program.statements[23].string(), "((1 + (2 + 3)) + 4)");
-        assert_eq!(program.statements[24].string(), "((5 + 5) * 2)");
-        assert_eq!(program.statements[25].string(), "(2 / (5 + 5))");
-        assert_eq!(program.statements[26].string(), "(-(5 + 5))");
-        assert_eq!(program.statements[27].string(), "(!(true == true))");
+        assert_eq!(statement_string(&program, 20), "(true == true)");
+        assert_eq!(statement_string(&program, 21), "(true != false)");
+        assert_eq!(statement_string(&program, 22), "(false == false)");
+        assert_eq!(statement_string(&program, 23), "((1 + (2 + 3)) + 4)");
+        assert_eq!(statement_string(&program, 24), "((5 + 5) * 2)");
+        assert_eq!(statement_string(&program, 25), "(2 / (5 + 5))");
+        assert_eq!(statement_string(&program, 26), "(-(5 + 5))");
+        assert_eq!(statement_string(&program, 27), "(!(true == true))");
+    }
+
+    #[test]
+    fn test_logical_and_comparison_precedence() {
+        let input = r#"
+a % b * c;
+a + b % c;
+a <= b == c >= d;
+a || b && c;
+a && b || c;
+a == b && c != d || e < f;
+!a && b;
+"#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert_eq!(statement_string(&program, 0), "((a % b) * c)");
+        assert_eq!(statement_string(&program, 1), "(a + (b % c))");
+        assert_eq!(statement_string(&program, 2), "((a <= b) == (c >= d))");
+        assert_eq!(statement_string(&program, 3), "(a || (b && c))");
+        assert_eq!(statement_string(&program, 4), "((a && b) || c)");
+        assert_eq!(
+            statement_string(&program, 5),
+            "(((a == b) && (c != d)) || (e < f))"
+        );
+        assert_eq!(statement_string(&program, 6), "((!a) && b)");
+    }
+
+    #[test]
+    fn test_bitwise_precedence() {
+        let input = r#"
+a | b ^ c & d;
+a & b | c ^ d;
+a << 1 + b;
+a < b << c;
+a & b == c;
+a || b | c && d;
+~a & -b;
+~~a;
+"#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert_eq!(statement_string(&program, 0), "(a | (b ^ (c & d)))");
+        assert_eq!(statement_string(&program, 1), "((a & b) | (c ^ d))");
+        assert_eq!(statement_string(&program, 2), "(a << (1 + b))");
+        assert_eq!(statement_string(&program, 3), "(a < (b << c))");
+        assert_eq!(statement_string(&program, 4), "(a & (b == c))");
+        assert_eq!(statement_string(&program, 5), "(a || ((b | c) && d))");
+        assert_eq!(statement_string(&program, 6), "((~a) & (-b))");
+        assert_eq!(statement_string(&program, 7), "(~(~a))");
+    }
+
+    #[test]
+    fn test_array_and_index_expressions() {
+        let input = "[1, 2 * 2, 3 + 3]; []; my_array[1 + 1]; a * [1, 2, 3, 4][b * c] * d; add(a * b[2], b[1]);";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert!(parser.errors.is_empty());
+        assert_eq!(statement_string(&program, 0), "[1, (2 * 2), (3 + 3)]");
+        assert_eq!(statement_string(&program, 1), "[]");
+        assert_eq!(statement_string(&program, 2), "(my_array[(1 + 1)])");
+        assert_eq!(
+            statement_string(&program, 3),
+            "((a * ([1, 2, 3, 4][(b * c)])) * d)"
+        );
+        assert_eq!(statement_string(&program, 4), "add((a * (b[2])), (b[1]))");
+    }
+
+    #[test]
+    fn test_assign_expression() {
+        let input = r#"
+x = 5;
+x = y = z + 1;
+x += 1;
+x -= y * 2;
+x *= 2;
+x /= 2;
+xs[i] = x == y;
+xs[0] += 1;
+"#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert!(parser.errors.is_empty());
+        assert_eq!(statement_string(&program, 0), "(x = 5)");
+        assert_eq!(statement_string(&program, 1), "(x = (y = (z + 1)))");
+        assert_eq!(statement_string(&program, 2), "(x += 1)");
+        assert_eq!(statement_string(&program, 3), "(x -= (y * 2))");
+        assert_eq!(statement_string(&program, 4), "(x *= 2)");
+        assert_eq!(statement_string(&program, 5), "(x /= 2)");
+        assert_eq!(statement_string(&program, 6), "((xs[i]) = (x == y))");
+        assert_eq!(statement_string(&program, 7), "((xs[0]) += 1)");
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let input = "a + b = 5;\nf() += 1;";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "1:7: invalid assignment target for =",
+                "2:5: invalid assignment target for +=",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loop_statements() {
+        let input = r#"
+while (i < 10) { i += 1; }
+for (x in [1, 2]) { if (x == 1) { continue; } break; };
+while (true) { for (y in ys) { break; } }
+"#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(program.statements.len(), 3);
+        assert_eq!(statement_string(&program, 0), "while (i < 10) { (i += 1) }");
+        assert_eq!(
+            statement_string(&program, 1),
+            "for (x in [1, 2]) { if (x == 1) { continue; }break; }"
+        );
+        assert_eq!(
+            statement_string(&program, 2),
+            "while (true) { for (y in ys) { break; } }"
+        );
+
+        // What is printed parses back to the same thing.
+        for index in 0..program.statements.len() {
+            let printed = statement_string(&program, index);
+            let mut parser = Parser::new(Lexer::new(&printed));
+            let reparsed = parser.parse();
+            assert!(parser.errors.is_empty(), "{}: {:?}", printed, parser.errors);
+            assert_eq!(statement_string(&reparsed, 0), printed);
+        }
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop() {
+        let input = r#"
+break;
+continue;
+while (true) { let f = fn() { break; }; }
+while (true) { fn() { while (false) { continue; } }; break; }
+"#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "2:1: break outside of a loop",
+                "3:1: continue outside of a loop",
+                "4:31: break outside of a loop",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_null_operators() {
+        let input = r#"
+null;
+a ?? b;
+a ?? b || c;
+a || b ?? c ?? d;
+x = a ?? b;
+xs?.[0];
+f?.(1, 2);
+a?.[0]?.(x)[1];
+-a?.[0];
+a ?? b?.[i] + 1;
+"#;
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(statement_string(&program, 0), "null");
+        assert_eq!(statement_string(&program, 1), "(a ?? b)");
+        assert_eq!(statement_string(&program, 2), "(a ?? (b || c))");
+        assert_eq!(statement_string(&program, 3), "(((a || b) ?? c) ?? d)");
+        assert_eq!(statement_string(&program, 4), "(x = (a ?? b))");
+        assert_eq!(statement_string(&program, 5), "(xs?.[0])");
+        assert_eq!(statement_string(&program, 6), "f?.(1, 2)");
+        assert_eq!(statement_string(&program, 7), "((a?.[0])?.(x)[1])");
+        assert_eq!(statement_string(&program, 8), "(-(a?.[0]))");
+        assert_eq!(statement_string(&program, 9), "(a ?? ((b?.[i]) + 1))");
+    }
+
+    #[test]
+    fn test_optional_chain_errors() {
+        let input = "a?.b;\nxs?.[0] = 1;";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "1:4: Unexpected token: Token{ kind: IDENTIFIER, literal: b }",
+                "2:9: invalid assignment target for =",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_literal() {
+        let input = r#""hello 世界" + "a\tb\"c\"\\";"#;
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(
+            statement_string(&program, 0),
+            format!("({})", input.trim_end_matches(';'))
+        );
+        let ExpressionNode::InfixExpression(infix) =
+            program.ast.expression(first_expression(&program))
+        else {
+            panic!("not an infix expression");
+        };
+        let ExpressionNode::StringLiteral(right) = program.ast.expression(infix.right) else {
+            panic!("not a string literal");
+        };
+        assert_eq!(right.value, "a\tb\"c\"\\");
+    }
+
+    #[test]
+    fn test_string_literal_errors() {
+        let input = "let s = \"bad \\q escape\";\nlet t = \"unterminated;";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "1:9: invalid escape sequence in string literal \"bad \\q escape\"",
+                "2:9: Unexpected token: Token{ kind: ILLEGAL, literal: \"unterminated; }",
+            ]
+        );
     }
 
     #[test]
     fn test_if_expression() {
         let input = "if (x < y) { x }";
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        assert_eq!(program.statements[0].string(), "if (x < y) { x }");
+        assert_eq!(statement_string(&program, 0), "if (x < y) { x }");
     }
 
     #[test]
     fn test_if_else_expression() {
         let input = "if (x < y) { x } else { y }";
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert_eq!(statement_string(&program, 0), "if (x < y) { x } else { y }");
+    }
+
+    #[test]
+    fn test_else_if_expression() {
+        let tests = [
+            "if (a < 1) { x } else if (b < 2) { y }",
+            "if (a < 1) { x } else if (b < 2) { y } else { z }",
+            "if (a < 1) { w } else if (b < 2) { x } else if (c < 3) { y } else { z }",
+            "if (a < 1) { if (b < 2) { x } else if (c < 3) { y } } else if (d < 4) { z }",
+            "if (a < 1) { x } else { if (b < 2) { y } }",
+            "if (a) { 1 } else if (b) { 2 }",
+            "if (ready()) { x } else if (xs[0]) { y } else if (true) { z }",
+            "if (!a) { x } else if (f(a)(b)) { y }",
+        ];
+
+        for input in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse();
+
+            assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+            assert_eq!(program.statements.len(), 1);
+            assert_eq!(statement_string(&program, 0), input);
+        }
+    }
+
+    #[test]
+    fn test_else_if_chain_structure() {
+        let input = "if (a) { 1 } else if (b) { 2 } else if (c) { 3 } else { 4 }";
+
+        let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
+        let if_expression = |id| match program.ast.expression(id) {
+            ExpressionNode::IfExpression(if_expression) => if_expression,
+            expression => panic!("not an if expression: {:?}", expression),
+        };
+        let mut current = if_expression(first_expression(&program));
+        let mut conditions = vec![program.ast[current.condition].string(&program.ast)];
+        while let Some(ElseBranch::If(next)) = current.alternative {
+            current = if_expression(next);
+            conditions.push(program.ast[current.condition].string(&program.ast));
+        }
+
+        assert_eq!(conditions, vec!["a", "b", "c"]);
+        assert!(matches!(current.alternative, Some(ElseBranch::Block(_))));
+    }
+
+    #[test]
+    fn test_else_if_errors() {
+        let input = "if (a) { 1 } else if { 2 }";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse();
+
         assert_eq!(
-            program.statements[0].string(),
-            "if (x < y) { x } else { y }"
+            parser.errors[0].to_string(),
+            "1:22: expected next token to be (, got { instead"
         );
     }
 
@@ -576,10 +1475,93 @@ false == false;
     fn test_function_literal() {
         let input = "fn(x, y) { x + y; }";
 
-        let lexer = tokenize(input);
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert_eq!(statement_string(&program, 0), "fn(x, y)(x + y)");
+    }
+
+    #[test]
+    fn test_call_expression() {
+        let input = "add(1, 2 * 3, 4 + 5); let x = f(); return g(h(1));";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse();
+
+        assert!(parser.errors.is_empty());
+        assert_eq!(statement_string(&program, 0), "add(1, (2 * 3), (4 + 5))");
+        assert_eq!(statement_string(&program, 1), "let x = f();");
+        assert_eq!(statement_string(&program, 2), "return g(h(1));");
+    }
+
+    #[test]
+    fn test_big_integer_literal() {
+        let input = "let x = 1 + 99999999999999999999;\n9223372036854775807;";
+
+        let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let program = parser.parse();
 
-        assert_eq!(program.statements[0].string(), "fn(x, y)(x + y)");
+        assert!(parser.errors.is_empty());
+        assert_eq!(program.statements.len(), 2);
+        assert_eq!(
+            statement_string(&program, 0),
+            "let x = (1 + 99999999999999999999);"
+        );
+        assert_eq!(statement_string(&program, 1), "9223372036854775807");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let input = "let = 5;\nlet x 5;";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors[0],
+            "1:5: expected next token to be IDENTIFIER, got = instead"
+        );
+        assert_eq!(
+            errors[1],
+            "2:7: expected next token to be =, got INTEGER instead"
+        );
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let input = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        let mut parser = Parser::new(Lexer::new(&input));
+        parser.parse();
+
+        let errors: Vec<String> = parser.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, ["1:257: nested more than 256 deep"]);
+
+        let input = "while (true) { ".repeat(100_000);
+        let mut parser = Parser::new(Lexer::new(&input));
+        parser.parse();
+        assert_eq!(
+            parser.errors[0].to_string(),
+            "1:3848: nested more than 256 deep"
+        );
+
+        let input = format!("if (a) {{ 1 }}{}", " else if (a) { 1 }".repeat(100_000));
+        let mut parser = Parser::new(Lexer::new(&input));
+        parser.parse();
+        assert_eq!(
+            parser.errors[0].to_string(),
+            "1:4582: nested more than 256 deep"
+        );
+
+        let input = format!("{}1{};", "(".repeat(255), ")".repeat(255));
+        let mut parser = Parser::new(Lexer::new(&input));
+        let program = parser.parse();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert_eq!(program.statements.len(), 1);
     }
 }
@@ -0,0 +1,453 @@
+use crate::ast::{Ast, BlockId, CallExpression, StatementId};
+use crate::environment::Environment;
+use crate::evaluator::{Hook, IntegerMode};
+use crate::interpreter::Interpreter;
+use crate::object::Object;
+use crate::token::Position;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Times every function a program calls. Install it as the evaluator's hook, then take the
+/// results with `into_profile` once the program has finished.
+#[derive(Debug)]
+pub struct Profiler {
+    state: RefCell<Profile>,
+}
+
+/// What a profiled run spent its time on.
+#[derive(Debug)]
+pub struct Profile {
+    /// The program itself first, then functions in the order they were first called.
+    pub functions: Vec<FunctionStats>,
+    // Functions are told apart by the arena and block their body is in.
+    ids: HashMap<(*const Ast, BlockId), usize>,
+    // The call tree, with the program itself at the root. A path through it is a stack.
+    nodes: Vec<Node>,
+    stack: Vec<Active>,
+}
+
+#[derive(Debug)]
+pub struct FunctionStats {
+    /// What the function was called as the first time, such as `add` or `handlers[0]`.
+    pub name: String,
+    /// Where the function literal's body starts.
+    pub defined: Position,
+    pub calls: u64,
+    /// Time spent in the function and everything it called. A recursive call's time is
+    /// only counted once, by the outermost call.
+    pub inclusive: Duration,
+    /// Time spent in the function's own statements.
+    pub exclusive: Duration,
+    /// Lines the function was called from.
+    pub call_sites: BTreeSet<usize>,
+    // Calls in progress, so recursion can be told apart from the outermost call.
+    active: usize,
+}
+
+#[derive(Debug)]
+struct Node {
+    function: usize,
+    children: HashMap<usize, usize>,
+    exclusive: Duration,
+}
+
+#[derive(Debug)]
+struct Active {
+    // `None` for a call to something that isn't a function, which fails at once.
+    function: Option<usize>,
+    node: usize,
+    start: Instant,
+    // Time spent in the calls this one made.
+    children: Duration,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let main = FunctionStats {
+            name: String::from("<main>"),
+            defined: Position::default(),
+            calls: 1,
+            inclusive: Duration::ZERO,
+            exclusive: Duration::ZERO,
+            call_sites: BTreeSet::new(),
+            active: 1,
+        };
+        Profiler {
+            state: RefCell::new(Profile {
+                functions: vec![main],
+                ids: HashMap::new(),
+                nodes: vec![Node {
+                    function: 0,
+                    children: HashMap::new(),
+                    exclusive: Duration::ZERO,
+                }],
+                stack: vec![Active {
+                    function: Some(0),
+                    node: 0,
+                    start: Instant::now(),
+                    children: Duration::ZERO,
+                }],
+            }),
+        }
+    }
+
+    /// Stops the clock on the program itself and hands back the results.
+    pub fn into_profile(self) -> Profile {
+        let mut profile = self.state.into_inner();
+        while !profile.stack.is_empty() {
+            profile.exit();
+        }
+        profile
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Hook for Profiler {
+    fn statement(
+        &self,
+        _statement: StatementId,
+        _ast: &Rc<Ast>,
+        _env: &Rc<RefCell<Environment>>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn enter(&self, call: &CallExpression, ast: &Rc<Ast>, function: &Object) {
+        let profile = &mut *self.state.borrow_mut();
+        let parent = profile
+            .stack
+            .last()
+            .expect("the program is always running")
+            .node;
+        let Object::Function(function) = function else {
+            profile.stack.push(Active {
+                function: None,
+                node: parent,
+                start: Instant::now(),
+                children: Duration::ZERO,
+            });
+            return;
+        };
+
+        let functions = &mut profile.functions;
+        let id = *profile
+            .ids
+            .entry((Rc::as_ptr(&function.ast), function.body))
+            .or_insert_with(|| {
+                functions.push(FunctionStats {
+                    name: ast[call.function].string(ast),
+                    defined: function.ast[function.body].token.span.start,
+                    calls: 0,
+                    inclusive: Duration::ZERO,
+                    exclusive: Duration::ZERO,
+                    call_sites: BTreeSet::new(),
+                    active: 0,
+                });
+                functions.len() - 1
+            });
+        let stats = &mut functions[id];
+        stats.calls += 1;
+        stats.active += 1;
+        stats
+            .call_sites
+            .insert(ast.expression_token(call.function).span.start.line);
+
+        let nodes = &mut profile.nodes;
+        let node = match nodes[parent].children.get(&id) {
+            Some(&node) => node,
+            None => {
+                nodes.push(Node {
+                    function: id,
+                    children: HashMap::new(),
+                    exclusive: Duration::ZERO,
+                });
+                let node = nodes.len() - 1;
+                nodes[parent].children.insert(id, node);
+                node
+            }
+        };
+        profile.stack.push(Active {
+            function: Some(id),
+            node,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn exit(&self, _result: &Object) {
+        self.state.borrow_mut().exit();
+    }
+}
+
+impl Profile {
+    fn exit(&mut self) {
+        let Some(active) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = active.start.elapsed();
+        if let Some(caller) = self.stack.last_mut() {
+            caller.children += elapsed;
+        }
+        let Some(id) = active.function else {
+            return;
+        };
+
+        let exclusive = elapsed.saturating_sub(active.children);
+        self.nodes[active.node].exclusive += exclusive;
+        let stats = &mut self.functions[id];
+        stats.exclusive += exclusive;
+        stats.active -= 1;
+        if stats.active == 0 {
+            stats.inclusive += elapsed;
+        }
+    }
+
+    // The name a function goes by in reports, with the line it was defined on to tell apart
+    // functions called by the same name.
+    fn label(&self, id: usize) -> String {
+        let stats = &self.functions[id];
+        match id {
+            0 => stats.name.clone(),
+            _ => format!("{}:{}", stats.name, stats.defined.line),
+        }
+    }
+
+    /// A table of every function, the one with the most time of its own first.
+    pub fn table(&self) -> String {
+        let mut order: Vec<usize> = (0..self.functions.len()).collect();
+        order.sort_by_key(|&id| std::cmp::Reverse(self.functions[id].exclusive));
+
+        let labels: Vec<String> = (0..self.functions.len()).map(|id| self.label(id)).collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0).max(8);
+
+        let mut out = format!(
+            "{:<width$}  {:>8}  {:>12}  {:>12}  call sites\n",
+            "function",
+            "calls",
+            "inclusive ms",
+            "exclusive ms",
+            width = width
+        );
+        for id in order {
+            let stats = &self.functions[id];
+            let call_sites: Vec<String> =
+                stats.call_sites.iter().map(ToString::to_string).collect();
+            let row = format!(
+                "{:<width$}  {:>8}  {:>12.3}  {:>12.3}  {}",
+                labels[id],
+                stats.calls,
+                stats.inclusive.as_secs_f64() * 1000.0,
+                stats.exclusive.as_secs_f64() * 1000.0,
+                call_sites.join(", "),
+                width = width
+            );
+            out += row.trim_end();
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Every stack the program ran, one per line as `<main>;outer;inner MICROSECONDS`, with
+    /// the time spent in the innermost function's own statements. This is the folded format
+    /// flamegraph tools read. Stacks that took under a microsecond of their own are left out.
+    pub fn folded(&self) -> String {
+        // Frames are separated by `;`, so one can't appear in a name.
+        let labels: Vec<String> = (0..self.functions.len())
+            .map(|id| self.label(id).replace(';', ","))
+            .collect();
+        let mut folded = String::new();
+        // The path to each node is built on the one to its parent, which is what's left of
+        // `path` once it's cut back to the parent's length.
+        let mut path = String::new();
+        let mut pending = vec![(0, 0)];
+        while let Some((node, parent_length)) = pending.pop() {
+            path.truncate(parent_length);
+            if parent_length > 0 {
+                path.push(';');
+            }
+            let node = &self.nodes[node];
+            path.push_str(&labels[node.function]);
+
+            let micros = node.exclusive.as_micros();
+            if micros > 0 {
+                folded.push_str(&format!("{} {}\n", path, micros));
+            }
+            // Pushed in reverse so they're popped, and printed, in order of name.
+            let mut children: Vec<usize> = node.children.values().copied().collect();
+            children.sort_by(|a, b| {
+                labels[self.nodes[*b].function].cmp(&labels[self.nodes[*a].function])
+            });
+            pending.extend(children.into_iter().map(|child| (child, path.len())));
+        }
+        folded
+    }
+}
+
+/// Runs `source` with a profiler and writes its table to `output`, or its folded stacks if
+/// `folded` is set.
+pub fn run(
+    source: &str,
+    integer_mode: IntegerMode,
+    folded: bool,
+    mut output: impl Write,
+) -> io::Result<()> {
+    let mut interpreter = Interpreter::new(integer_mode);
+    let program = match interpreter.compile(source) {
+        Ok((program, warnings)) => {
+            for warning in &warnings {
+                writeln!(output, "{}", warning)?;
+            }
+            program
+        }
+        Err(error) => return writeln!(output, "{}", error),
+    };
+
+    let (result, profiler) = interpreter.run_hooked(&program, Profiler::new());
+    let heap = interpreter.heap_stats();
+    let profile = profiler.into_profile();
+    if folded {
+        write!(output, "{}", profile.folded())
+    } else {
+        writeln!(output, "{}\n", result)?;
+        write!(output, "{}", profile.table())?;
+        writeln!(output, "\n{}", heap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    fn profile(source: &str) -> Profile {
+        let mut program = Parser::new(Lexer::new(source)).parse();
+        Resolver::new().resolve(&mut program);
+        let profiler = Rc::new(Profiler::new());
+        let mut evaluator = Evaluator::new(IntegerMode::default());
+        evaluator.hook = Some(profiler.clone());
+        evaluator.eval_program(&program, &evaluator.new_environment());
+        drop(evaluator);
+        Rc::try_unwrap(profiler).unwrap().into_profile()
+    }
+
+    const PROGRAM: &str = "let fib = fn(n) {
+  if (n < 2) { return n; }
+  fib(n - 1) + fib(n - 2)
+};
+let square = fn(x) { x * x };
+let total = fib(10) + square(3);
+square(total)";
+
+    #[test]
+    fn test_counts_and_call_sites() {
+        let profile = profile(PROGRAM);
+        let summary: Vec<(&str, u64, Vec<usize>)> = profile
+            .functions
+            .iter()
+            .map(|stats| {
+                (
+                    stats.name.as_str(),
+                    stats.calls,
+                    stats.call_sites.iter().copied().collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("<main>", 1, vec![]),
+                ("fib", 177, vec![3, 6]),
+                ("square", 2, vec![6, 7]),
+            ]
+        );
+
+        for stats in &profile.functions {
+            assert!(stats.exclusive <= stats.inclusive, "{:?}", stats);
+        }
+        let main = &profile.functions[0];
+        assert!(main.inclusive >= profile.functions[1].inclusive);
+    }
+
+    #[test]
+    fn test_table() {
+        let table = profile(PROGRAM).table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("function"));
+        assert!(lines[0].ends_with("calls  inclusive ms  exclusive ms  call sites"));
+
+        let fib = lines
+            .iter()
+            .find(|line| line.starts_with("fib:1 "))
+            .unwrap();
+        assert!(fib.contains(" 177 "));
+        assert!(fib.ends_with("  3, 6"));
+        let square = lines
+            .iter()
+            .find(|line| line.starts_with("square:5 "))
+            .unwrap();
+        assert!(square.ends_with("  6, 7"));
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let folded = profile(PROGRAM).folded();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        // Stacks that took under a microsecond of their own are left out.
+        let mut expected = [
+            "<main>",
+            "<main>;fib:1",
+            "<main>;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1",
+            "<main>;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1;fib:1",
+            "<main>;square:5",
+        ]
+        .into_iter();
+        for stack in stacks {
+            assert!(expected.any(|expected| expected == stack), "{}", folded);
+        }
+        for line in folded.lines() {
+            let (_, micros) = line.rsplit_once(' ').unwrap();
+            assert!(micros.parse::<u128>().unwrap() > 0);
+        }
+    }
+
+    #[test]
+    fn test_failed_calls_keep_the_stack_balanced() {
+        let profile = profile("let f = fn() { 1 };\nlet x = 5;\nf() + x(1) + f()");
+        assert_eq!(profile.functions.len(), 2);
+        assert_eq!(profile.functions[1].calls, 1);
+        assert!(profile.stack.is_empty());
+    }
+
+    #[test]
+    fn test_tail_calls_replace_the_caller() {
+        let profile = profile(
+            "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } };
+countdown(100000)",
+        );
+        assert_eq!(profile.functions[1].calls, 100001);
+        assert_eq!(profile.nodes.len(), 2);
+        assert!(profile.folded().lines().count() <= 2);
+    }
+}
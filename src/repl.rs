@@ -1,13 +1,39 @@
-use crate::lexer::tokenize;
+use crate::evaluator::IntegerMode;
+use crate::interpreter::Interpreter;
+use crate::limits::Limits;
 use std::io::{self, Write};
 
-pub fn start() {
+pub fn start(integer_mode: IntegerMode, limits: Limits, optimize: bool) {
+    let mut interpreter = Interpreter::new(integer_mode);
+    interpreter.set_limits(limits);
+
     loop {
         print!(">> ");
         io::stdout().flush().unwrap();
         let mut line = String::new();
-        io::stdin().read_line(&mut line).unwrap();
-        let tokens = tokenize(&line);
-        println!("{:?}", tokens);
+        if io::stdin().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        let mut program = match interpreter.compile(&line) {
+            Ok((program, warnings)) => {
+                for warning in &warnings {
+                    println!("\t{}", warning);
+                }
+                program
+            }
+            Err(error) => {
+                for line in error.to_string().lines() {
+                    println!("\t{}", line);
+                }
+                continue;
+            }
+        };
+
+        if optimize {
+            interpreter.optimize(&mut program);
+        }
+
+        println!("{}", interpreter.run(&program));
     }
 }
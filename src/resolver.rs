@@ -0,0 +1,694 @@
+use crate::ast::*;
+use crate::environment::Environment;
+use crate::evaluator::IntegerMode;
+use crate::object::Builtin;
+use crate::symbol::Symbol;
+use crate::token::{Span, Token};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum Diagnostic {
+    UndefinedIdentifier(Token<'static>),
+    DuplicateParameter(Token<'static>),
+    /// An integer literal too big for an `i64`, in a mode without arbitrary precision.
+    IntegerOutOfRange(Token<'static>),
+    /// A declaration hiding one from an enclosing scope. Only a warning.
+    Shadowing {
+        token: Token<'static>,
+        shadowed: Span,
+    },
+}
+
+impl Diagnostic {
+    pub fn span(&self) -> Span {
+        match self {
+            Diagnostic::UndefinedIdentifier(token)
+            | Diagnostic::DuplicateParameter(token)
+            | Diagnostic::IntegerOutOfRange(token)
+            | Diagnostic::Shadowing { token, .. } => token.span,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Diagnostic::Shadowing { .. })
+    }
+
+    /// The problem without its position or severity.
+    pub fn message(&self) -> String {
+        match self {
+            Diagnostic::UndefinedIdentifier(token) => {
+                format!("undefined identifier: {}", token.literal)
+            }
+            Diagnostic::DuplicateParameter(token) => {
+                format!("duplicate parameter: {}", token.literal)
+            }
+            Diagnostic::IntegerOutOfRange(token) => format!(
+                "integer literal {} is out of range for a 64-bit integer",
+                token.literal
+            ),
+            Diagnostic::Shadowing { token, shadowed } => format!(
+                "{} shadows the binding declared at {}",
+                token.literal, shadowed.start
+            ),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.span().start)?;
+        if !self.is_error() {
+            write!(f, "warning: ")?;
+        }
+        write!(f, "{}", self.message())
+    }
+}
+
+/// The node a binding is declared by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Declarer {
+    Let(StatementId),
+    /// A parameter of the function literal.
+    Parameter(ExpressionId),
+    LoopVariable(StatementId),
+    /// A binding made outside the source being resolved, such as a global the host
+    /// embedding the language defined, or a local of code that is already running.
+    Host,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Declaration {
+    pub name: Symbol,
+    pub span: Span,
+    pub declarer: Declarer,
+    /// The body the binding is visible in, or `None` for the top level.
+    pub scope: Option<BlockId>,
+    slot: usize,
+}
+
+impl Declaration {
+    fn host(name: Symbol, slot: usize) -> Self {
+        Declaration {
+            name,
+            span: Span::default(),
+            declarer: Declarer::Host,
+            scope: None,
+            slot,
+        }
+    }
+}
+
+/// An identifier and the declaration it refers to. Declarations refer to themselves.
+#[derive(Debug, Clone, Copy)]
+pub struct Reference {
+    pub span: Span,
+    pub declaration: Declaration,
+}
+
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub diagnostics: Vec<Diagnostic>,
+    /// Every resolved identifier, in the order they were visited. Top-level declarations
+    /// remembered from earlier programs keep ids into those programs' trees.
+    pub references: Vec<Reference>,
+}
+
+// The scopes mirror the environments the evaluator creates: the top level, one per function
+// call and one per loop iteration. `if` blocks declare into the scope around them.
+#[derive(Debug, Default)]
+struct Scope {
+    block: Option<BlockId>,
+    names: HashMap<Symbol, Declaration>,
+    // The slot the next new name gets.
+    next_slot: usize,
+    // Function bodies are resolved once the scope around them is complete, since they only
+    // run after the declarations that follow them.
+    functions: Vec<ExpressionId>,
+}
+
+/// Checks that every identifier refers to a declaration and records in the tree where each
+/// one lives at run time.
+///
+/// Top-level declarations are remembered between programs, so the REPL can resolve one line
+/// at a time.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    // The mode programs will run in, which decides the integer literals that fit.
+    integer_mode: IntegerMode,
+    globals: HashMap<Symbol, Declaration>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver::default()
+    }
+
+    /// A resolver for programs that will run in `integer_mode`, which reports the integer
+    /// literals that don't fit in it.
+    pub fn with_integer_mode(integer_mode: IntegerMode) -> Self {
+        Resolver {
+            integer_mode,
+            ..Resolver::default()
+        }
+    }
+
+    /// Declares a global defined outside of any program, so that programs can refer to it.
+    pub fn declare_global(&mut self, name: Symbol) {
+        let slot = self.globals.len();
+        self.globals
+            .entry(name)
+            .or_insert(Declaration::host(name, slot));
+    }
+
+    /// Annotates the identifiers in `program` with their bindings and reports the problems
+    /// found, in source order within each function body.
+    pub fn resolve(&mut self, program: &mut Program) -> Resolution {
+        let globals = mem::take(&mut self.globals);
+        let global = Scope {
+            next_slot: globals.len(),
+            names: globals,
+            ..Scope::default()
+        };
+        let (resolution, global) = self.run(program, vec![global]);
+        self.globals = global.names;
+        resolution
+    }
+
+    /// Resolves `program` to run in `env`, which can be the scope of a call, such as one a
+    /// debugger is paused in. The program can refer to what `env` and the scopes around it
+    /// have bound so far, and declares into `env`.
+    pub fn resolve_in(&self, program: &mut Program, env: &Rc<RefCell<Environment>>) -> Resolution {
+        let mut scopes = Vec::new();
+        let mut current = Some(Rc::clone(env));
+        while let Some(env) = current {
+            let env = env.borrow();
+            let scope = match env.outer() {
+                Some(_) => Scope {
+                    names: env
+                        .local_names()
+                        .map(|(slot, name)| (name, Declaration::host(name, slot)))
+                        .collect(),
+                    next_slot: env.slot_count(),
+                    ..Scope::default()
+                },
+                None => Scope {
+                    names: env
+                        .global_names()
+                        .enumerate()
+                        .map(|(slot, name)| (name, Declaration::host(name, slot)))
+                        .collect(),
+                    ..Scope::default()
+                },
+            };
+            scopes.push(scope);
+            current = env.outer().cloned();
+        }
+        scopes.reverse();
+        self.run(program, scopes).0
+    }
+
+    // Resolves `program` in `scopes`, the global scope first, then closes them all and hands
+    // back the global one.
+    fn run(&self, program: &mut Program, scopes: Vec<Scope>) -> (Resolution, Scope) {
+        let mut pass = Pass {
+            ast: Rc::make_mut(&mut program.ast),
+            scopes,
+            resolution: Resolution::default(),
+            integer_mode: self.integer_mode,
+        };
+
+        for statement in &program.statements {
+            pass.resolve_statement(*statement);
+        }
+        let mut global = pass.pop_scope();
+        while !pass.scopes.is_empty() {
+            global = pass.pop_scope();
+        }
+        (pass.resolution, global)
+    }
+}
+
+struct Pass<'a> {
+    ast: &'a mut Ast,
+    scopes: Vec<Scope>,
+    resolution: Resolution,
+    integer_mode: IntegerMode,
+}
+
+impl Pass<'_> {
+    fn resolve_statement(&mut self, id: StatementId) {
+        match self.ast.statement(id) {
+            StatementNode::LetStatement(statement) => {
+                // The value is resolved first, so `let x = x` refers to an outer `x`.
+                let mut statement = statement.clone();
+                self.resolve_expression(statement.value);
+                self.declare(&mut statement.name, Declarer::Let(id));
+                self.ast.replace_statement(id, statement);
+            }
+            StatementNode::ReturnStatement(statement) => {
+                self.resolve_expression(statement.return_value)
+            }
+            StatementNode::ExpressionStatement(statement) => {
+                self.resolve_expression(statement.expression)
+            }
+            StatementNode::WhileStatement(statement) => {
+                let (condition, body) = (statement.condition, statement.body);
+                self.resolve_expression(condition);
+                self.push_scope(body);
+                self.resolve_block(body);
+                self.pop_scope();
+            }
+            StatementNode::ForStatement(statement) => {
+                let mut statement = statement.clone();
+                self.resolve_expression(statement.iterable);
+                self.push_scope(statement.body);
+                self.declare(&mut statement.variable, Declarer::LoopVariable(id));
+                self.resolve_block(statement.body);
+                self.pop_scope();
+                self.ast.replace_statement(id, statement);
+            }
+            StatementNode::BreakStatement(_) | StatementNode::ContinueStatement(_) => {}
+        }
+    }
+
+    fn resolve_block(&mut self, id: BlockId) {
+        for statement in self.ast[id].statements.clone() {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_expression(&mut self, id: ExpressionId) {
+        match self.ast.expression(id) {
+            ExpressionNode::Identifier(identifier) => {
+                let mut identifier = identifier.clone();
+                match self.lookup(identifier.value, identifier.token.span) {
+                    Some(binding) => {
+                        identifier.binding = Some(binding);
+                        self.ast.replace_expression(id, identifier);
+                    }
+                    None => self
+                        .resolution
+                        .diagnostics
+                        .push(Diagnostic::UndefinedIdentifier(identifier.token)),
+                }
+            }
+            ExpressionNode::IntegerLiteral(integer) => {
+                if self.integer_mode != IntegerMode::Arbitrary
+                    && i64::try_from(&integer.value).is_err()
+                {
+                    self.resolution
+                        .diagnostics
+                        .push(Diagnostic::IntegerOutOfRange(integer.token.clone()));
+                }
+            }
+            ExpressionNode::StringLiteral(_)
+            | ExpressionNode::Boolean(_)
+            | ExpressionNode::NullLiteral(_) => {}
+            ExpressionNode::PrefixExpression(prefix) => self.resolve_expression(prefix.right),
+            ExpressionNode::InfixExpression(infix) => {
+                let (left, right) = (infix.left, infix.right);
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            ExpressionNode::IfExpression(if_expression) => {
+                let if_expression = if_expression.clone();
+                self.resolve_expression(if_expression.condition);
+                self.resolve_block(if_expression.consequence);
+                match if_expression.alternative {
+                    Some(ElseBranch::Block(alternative)) => self.resolve_block(alternative),
+                    Some(ElseBranch::If(alternative)) => self.resolve_expression(alternative),
+                    None => {}
+                }
+            }
+            ExpressionNode::FunctionLiteral(_) => self.current_scope().functions.push(id),
+            ExpressionNode::CallExpression(call) => {
+                let call = call.clone();
+                self.resolve_expression(call.function);
+                for argument in call.arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            ExpressionNode::ArrayLiteral(array) => {
+                for element in array.elements.clone() {
+                    self.resolve_expression(element);
+                }
+            }
+            ExpressionNode::IndexExpression(index) => {
+                let (left, index) = (index.left, index.index);
+                self.resolve_expression(left);
+                self.resolve_expression(index);
+            }
+            ExpressionNode::AssignExpression(assign) => {
+                let (target, value) = (assign.target, assign.value);
+                self.resolve_expression(target);
+                self.resolve_expression(value);
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, id: ExpressionId) {
+        let ExpressionNode::FunctionLiteral(function) = self.ast.expression(id) else {
+            return;
+        };
+        let mut function = function.clone();
+
+        self.push_scope(function.body);
+        for parameter in &mut function.parameters {
+            if self.current_scope().names.contains_key(&parameter.value) {
+                self.resolution
+                    .diagnostics
+                    .push(Diagnostic::DuplicateParameter(parameter.token.clone()));
+            }
+            self.declare(parameter, Declarer::Parameter(id));
+        }
+        self.resolve_block(function.body);
+        self.pop_scope();
+
+        self.ast.replace_expression(id, function);
+    }
+
+    // Declaring a name again in the same scope reuses its slot, as `let` overwrites it.
+    fn declare(&mut self, identifier: &mut Identifier, declarer: Declarer) {
+        let span = identifier.token.span;
+        let outer = self.scopes.len() - 1;
+
+        let slot = match self.scopes[outer].names.get(&identifier.value) {
+            Some(declaration) => declaration.slot,
+            None => {
+                let shadowed = self.scopes[..outer]
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.names.get(&identifier.value));
+                if let Some(shadowed) = shadowed {
+                    self.resolution.diagnostics.push(Diagnostic::Shadowing {
+                        token: identifier.token.clone(),
+                        shadowed: shadowed.span,
+                    });
+                }
+                let slot = self.scopes[outer].next_slot;
+                self.scopes[outer].next_slot += 1;
+                slot
+            }
+        };
+        let declaration = Declaration {
+            name: identifier.value,
+            span,
+            declarer,
+            scope: self.scopes[outer].block,
+            slot,
+        };
+        self.scopes[outer]
+            .names
+            .insert(identifier.value, declaration);
+        self.resolution
+            .references
+            .push(Reference { span, declaration });
+
+        identifier.binding = Some(if outer == 0 {
+            Binding::Global
+        } else {
+            Binding::Local { depth: 0, slot }
+        });
+    }
+
+    fn lookup(&mut self, name: Symbol, span: Span) -> Option<Binding> {
+        let innermost = self.scopes.len() - 1;
+        let found = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, scope)| Some((index, *scope.names.get(&name)?)));
+        let Some((index, declaration)) = found else {
+            return Builtin::lookup(name).map(|_| Binding::Global);
+        };
+        self.resolution
+            .references
+            .push(Reference { span, declaration });
+
+        Some(if index == 0 {
+            Binding::Global
+        } else {
+            Binding::Local {
+                depth: innermost - index,
+                slot: declaration.slot,
+            }
+        })
+    }
+
+    fn push_scope(&mut self, block: BlockId) {
+        self.scopes.push(Scope {
+            block: Some(block),
+            ..Scope::default()
+        });
+    }
+
+    fn current_scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("the global scope is never popped early")
+    }
+
+    // Resolves the functions left waiting on the scope, then closes it.
+    fn pop_scope(&mut self) -> Scope {
+        loop {
+            let functions = mem::take(&mut self.current_scope().functions);
+            if functions.is_empty() {
+                break;
+            }
+            for function in functions {
+                self.resolve_function(function);
+            }
+        }
+        self.scopes
+            .pop()
+            .expect("scopes are pushed and popped in pairs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Environment;
+    use crate::evaluator::Evaluator;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use std::cell::RefCell;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        program
+    }
+
+    fn diagnostics(input: &str) -> Vec<String> {
+        let mut program = parse(input);
+        Resolver::new()
+            .resolve(&mut program)
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    // Each identifier use in the program with its binding, in the order they were parsed.
+    fn bindings(program: &Program) -> Vec<(String, Option<Binding>)> {
+        program
+            .ast
+            .expression_ids()
+            .filter_map(|id| match program.ast.expression(id) {
+                ExpressionNode::Identifier(identifier) => Some(identifier),
+                _ => None,
+            })
+            .map(|identifier| (identifier.value.to_string(), identifier.binding))
+            .collect()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let tests: Vec<(&str, Vec<&str>)> = vec![
+            ("let x = 1; x + 1", vec![]),
+            ("x", vec!["1:1: undefined identifier: x"]),
+            ("let x = 1; y + x", vec!["1:12: undefined identifier: y"]),
+            ("let x = x;", vec!["1:9: undefined identifier: x"]),
+            ("y; let y = 1;", vec!["1:1: undefined identifier: y"]),
+            ("z = 3", vec!["1:1: undefined identifier: z"]),
+            ("fn(a, b, a) { a }", vec!["1:10: duplicate parameter: a"]),
+            (
+                "let f = fn(n) { n + m };",
+                vec!["1:21: undefined identifier: m"],
+            ),
+            (
+                "let x = 1; let f = fn(x) { x };",
+                vec!["1:23: warning: x shadows the binding declared at 1:5"],
+            ),
+            (
+                "let f = fn(a) { fn() { let a = 2; a } };",
+                vec!["1:28: warning: a shadows the binding declared at 1:12"],
+            ),
+            (
+                "for (i in [1]) { for (i in [2]) { i } }",
+                vec!["1:23: warning: i shadows the binding declared at 1:6"],
+            ),
+            // Declaring a name again in the same scope only rebinds it.
+            ("let x = 1; let x = x + 1; x", vec![]),
+            ("let f = fn(x) { let x = x * 2; x };", vec![]),
+            // Functions can refer to declarations that follow them.
+            (
+                "let even = fn(n) { odd(n) }; let odd = fn(n) { even(n) };",
+                vec![],
+            ),
+            ("let f = fn() { f() };", vec![]),
+            // `if` blocks declare into the scope around them, but loop bodies don't.
+            ("if (true) { let y = 1; } y", vec![]),
+            (
+                "while (false) { let y = 1; } y",
+                vec!["1:30: undefined identifier: y"],
+            ),
+            (
+                "for (i in []) { i } i",
+                vec!["1:21: undefined identifier: i"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(diagnostics(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_integer_literals_out_of_range() {
+        let input = "let f = fn() { 99999999999999999999 }; 9223372036854775807";
+        for (integer_mode, expected) in [
+            (IntegerMode::Arbitrary, vec![]),
+            (
+                IntegerMode::Checked,
+                vec!["1:16: integer literal 99999999999999999999 is out of range for a 64-bit integer"],
+            ),
+            (
+                IntegerMode::Wrapping,
+                vec!["1:16: integer literal 99999999999999999999 is out of range for a 64-bit integer"],
+            ),
+        ] {
+            let mut program = parse(input);
+            let diagnostics: Vec<String> = Resolver::with_integer_mode(integer_mode)
+                .resolve(&mut program)
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect();
+            assert_eq!(diagnostics, expected, "{:?}", integer_mode);
+        }
+    }
+
+    #[test]
+    fn test_bindings() {
+        let mut program = parse(
+            "let x = 1;
+             let f = fn(a) { let b = a; fn(c) { a + b + c + x } };
+             for (i in [1]) { while (i) { let j = i; j; } }",
+        );
+        let diagnostics = Resolver::new().resolve(&mut program).diagnostics;
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let local = |depth, slot| Some(Binding::Local { depth, slot });
+        let expected = vec![
+            ("a", local(0, 0)),
+            ("a", local(1, 0)),
+            ("b", local(1, 1)),
+            ("c", local(0, 0)),
+            ("x", Some(Binding::Global)),
+            ("i", local(0, 0)),
+            ("i", local(1, 0)),
+            ("j", local(0, 0)),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(name, binding)| (name.to_string(), binding))
+            .collect();
+        assert_eq!(bindings(&program), expected);
+    }
+
+    #[test]
+    fn test_globals_persist_between_programs() {
+        let mut resolver = Resolver::new();
+
+        let mut first = parse("let x = 1;");
+        assert!(resolver.resolve(&mut first).diagnostics.is_empty());
+
+        let mut second = parse("let f = fn(x) { x }; x");
+        let diagnostics: Vec<String> = resolver
+            .resolve(&mut second)
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec!["1:12: warning: x shadows the binding declared at 1:5"]
+        );
+        assert_eq!(bindings(&second).last().unwrap().1, Some(Binding::Global));
+    }
+
+    #[test]
+    fn test_resolved_programs_evaluate() {
+        let tests = [
+            (
+                "let x = 1; let f = fn(a) { let b = a; fn(c) { a + b + c + x } }; f(2)(3)",
+                "8",
+            ),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                "610",
+            ),
+            (
+                "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+                 [even(10), odd(7)]",
+                "[true, true]",
+            ),
+            (
+                "let counter = fn() { let n = 0; fn() { n += 1; n } };
+                 let c = counter(); c(); c(); c()",
+                "3",
+            ),
+            (
+                "let total = 0; for (i in [1, 2, 3]) { let sq = i * i; total += sq; } total",
+                "14",
+            ),
+            (
+                "let i = 0; let last = 0; while (i < 3) { let j = i * 2; last = j; i += 1; } [i, last]",
+                "[3, 4]",
+            ),
+            ("let x = 1; let f = fn(x) { x = x + 10; x }; [f(5), x]", "[15, 1]"),
+            ("let f = fn() { if (false) { let y = 1; } y }; f()",
+                "ERROR: 1:42: identifier not found: y",
+            ),
+            ("let f = fn() { if (false) { let y = 1; } y = 2 }; f()", "ERROR: 1:42: assignment to undeclared variable: y"),
+            ("let x = 1; let x = x + 1; x", "2"),
+            ("let g = fn() { h() }; g(); let h = fn() { 1 };", "ERROR: 1:16: identifier not found: h"),
+            ("let xs = [1, 2]; let f = fn(i) { xs[i] = i * 10; xs }; f(1)", "[1, 10]"),
+        ];
+
+        let evaluator = Evaluator::default();
+        for (input, expected) in tests {
+            let mut program = parse(input);
+            let diagnostics = Resolver::new().resolve(&mut program).diagnostics;
+            assert!(
+                diagnostics.iter().all(|diagnostic| !diagnostic.is_error()),
+                "{}: {:?}",
+                input,
+                diagnostics
+            );
+
+            let env = Rc::new(RefCell::new(Environment::new()));
+            let actual = evaluator.eval_program(&program, &env).to_string();
+            assert_eq!(actual, expected, "{}", input);
+        }
+    }
+}
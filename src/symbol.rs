@@ -0,0 +1,155 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// An interned identifier or keyword. Symbols compare and hash as integers, and `as_str`
+/// resolves them back to their text.
+///
+/// The interner is per thread, so symbols can't be sent to another. Names are kept for as
+/// long as the thread runs, so a symbol stays valid wherever it is copied to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32, PhantomData<*const ()>);
+
+/// Symbols for the keywords, which are interned before anything else.
+pub mod kw {
+    use super::Symbol;
+
+    pub const FN: Symbol = Symbol::new(0);
+    pub const LET: Symbol = Symbol::new(1);
+    pub const TRUE: Symbol = Symbol::new(2);
+    pub const FALSE: Symbol = Symbol::new(3);
+    pub const NULL: Symbol = Symbol::new(4);
+    pub const IF: Symbol = Symbol::new(5);
+    pub const ELSE: Symbol = Symbol::new(6);
+    pub const RETURN: Symbol = Symbol::new(7);
+    pub const WHILE: Symbol = Symbol::new(8);
+    pub const FOR: Symbol = Symbol::new(9);
+    pub const IN: Symbol = Symbol::new(10);
+    pub const BREAK: Symbol = Symbol::new(11);
+    pub const CONTINUE: Symbol = Symbol::new(12);
+}
+
+/// The keywords, in the same order as the constants in `kw`.
+pub const KEYWORDS: [&str; 13] = [
+    "fn", "let", "true", "false", "null", "if", "else", "return", "while", "for", "in", "break",
+    "continue",
+];
+
+struct Interner {
+    names: Vec<Rc<str>>,
+    symbols: HashMap<Rc<str>, Symbol>,
+}
+
+impl Interner {
+    fn new() -> Self {
+        let mut interner = Interner {
+            names: Vec::new(),
+            symbols: HashMap::new(),
+        };
+        for keyword in KEYWORDS {
+            interner.insert(keyword.into());
+        }
+        interner
+    }
+
+    fn insert(&mut self, name: Rc<str>) -> Symbol {
+        let symbol = Symbol::new(self.names.len() as u32);
+        self.names.push(Rc::clone(&name));
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        match self.symbols.get(name) {
+            Some(symbol) => *symbol,
+            None => self.insert(name.into()),
+        }
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl Symbol {
+    const fn new(index: u32) -> Symbol {
+        Symbol(index, PhantomData)
+    }
+
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    /// The symbol for `name` if it has been interned, without interning it otherwise.
+    pub fn lookup(name: &str) -> Option<Symbol> {
+        INTERNER.with(|interner| interner.borrow().symbols.get(name).copied())
+    }
+
+    pub fn as_str(self) -> Rc<str> {
+        INTERNER.with(|interner| Rc::clone(&interner.borrow().names[self.0 as usize]))
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Symbol({:?})", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keywords_are_preinterned() {
+        let keywords = [
+            (kw::FN, "fn"),
+            (kw::LET, "let"),
+            (kw::TRUE, "true"),
+            (kw::FALSE, "false"),
+            (kw::NULL, "null"),
+            (kw::IF, "if"),
+            (kw::ELSE, "else"),
+            (kw::RETURN, "return"),
+            (kw::WHILE, "while"),
+            (kw::FOR, "for"),
+            (kw::IN, "in"),
+            (kw::BREAK, "break"),
+            (kw::CONTINUE, "continue"),
+        ];
+
+        for (symbol, text) in keywords {
+            assert_eq!(&*symbol.as_str(), text);
+            assert_eq!(Symbol::intern(text), symbol);
+        }
+    }
+
+    #[test]
+    fn test_intern() {
+        let a = Symbol::intern("größe");
+        let b = Symbol::intern("合計");
+
+        assert_ne!(a, b);
+        assert_eq!(Symbol::intern("größe"), a);
+        assert_eq!(&*a.as_str(), "größe");
+        assert_eq!(b.to_string(), "合計");
+    }
+
+    #[test]
+    fn test_lookup_does_not_intern() {
+        assert_eq!(Symbol::lookup("let"), Some(kw::LET));
+        assert_eq!(Symbol::lookup("never_interned"), None);
+        assert_eq!(Symbol::lookup("never_interned"), None);
+
+        let symbol = Symbol::intern("interned_later");
+        assert_eq!(Symbol::lookup("interned_later"), Some(symbol));
+    }
+}
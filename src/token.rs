@@ -1,40 +1,168 @@
+use crate::symbol::Symbol;
+use std::borrow::Cow;
 use std::fmt::{self, Display};
 
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone, Hash)]
 pub enum TokenKind {
     ILLEGAL,
     EOF,
     INTEGER,
+    STRING,
     IDENTIFIER,
     ASSIGN,
+    PLUS_ASSIGN,
+    MINUS_ASSIGN,
+    ASTERISK_ASSIGN,
+    SLASH_ASSIGN,
     PLUS,
     MINUS,
     BANG,
     ASTERISK,
     SLASH,
+    PERCENT,
     LT,
     GT,
+    LT_EQ,
+    GT_EQ,
     EQ,
     NOT_EQ,
+    AND,
+    OR,
+    AMPERSAND,
+    PIPE,
+    CARET,
+    TILDE,
+    LSHIFT,
+    RSHIFT,
+    NULL_COALESCE,
+    OPTIONAL_CHAIN,
     COMMA,
     SEMICOLON,
     LPAREN,
     RPAREN,
     LBRACE,
     RBRACE,
+    LBRACKET,
+    RBRACKET,
     FUNCTION,
     LET,
     TRUE,
     FALSE,
+    NULL,
     IF,
     ELSE,
     RETURN,
+    WHILE,
+    FOR,
+    IN,
+    BREAK,
+    CONTINUE,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct Token {
+impl TokenKind {
+    /// The text every token of this kind is spelled with, or `None` for the kinds whose text
+    /// varies, such as identifiers.
+    pub fn spelling(&self) -> Option<&'static str> {
+        let spelling = match self {
+            TokenKind::ILLEGAL | TokenKind::INTEGER | TokenKind::STRING | TokenKind::IDENTIFIER => {
+                return None
+            }
+            TokenKind::EOF => "",
+            TokenKind::ASSIGN => "=",
+            TokenKind::PLUS_ASSIGN => "+=",
+            TokenKind::MINUS_ASSIGN => "-=",
+            TokenKind::ASTERISK_ASSIGN => "*=",
+            TokenKind::SLASH_ASSIGN => "/=",
+            TokenKind::PLUS => "+",
+            TokenKind::MINUS => "-",
+            TokenKind::BANG => "!",
+            TokenKind::ASTERISK => "*",
+            TokenKind::SLASH => "/",
+            TokenKind::PERCENT => "%",
+            TokenKind::LT => "<",
+            TokenKind::GT => ">",
+            TokenKind::LT_EQ => "<=",
+            TokenKind::GT_EQ => ">=",
+            TokenKind::EQ => "==",
+            TokenKind::NOT_EQ => "!=",
+            TokenKind::AND => "&&",
+            TokenKind::OR => "||",
+            TokenKind::AMPERSAND => "&",
+            TokenKind::PIPE => "|",
+            TokenKind::CARET => "^",
+            TokenKind::TILDE => "~",
+            TokenKind::LSHIFT => "<<",
+            TokenKind::RSHIFT => ">>",
+            TokenKind::NULL_COALESCE => "??",
+            TokenKind::OPTIONAL_CHAIN => "?.",
+            TokenKind::COMMA => ",",
+            TokenKind::SEMICOLON => ";",
+            TokenKind::LPAREN => "(",
+            TokenKind::RPAREN => ")",
+            TokenKind::LBRACE => "{",
+            TokenKind::RBRACE => "}",
+            TokenKind::LBRACKET => "[",
+            TokenKind::RBRACKET => "]",
+            TokenKind::FUNCTION => "fn",
+            TokenKind::LET => "let",
+            TokenKind::TRUE => "true",
+            TokenKind::FALSE => "false",
+            TokenKind::NULL => "null",
+            TokenKind::IF => "if",
+            TokenKind::ELSE => "else",
+            TokenKind::RETURN => "return",
+            TokenKind::WHILE => "while",
+            TokenKind::FOR => "for",
+            TokenKind::IN => "in",
+            TokenKind::BREAK => "break",
+            TokenKind::CONTINUE => "continue",
+        };
+        Some(spelling)
+    }
+}
+
+/// A location in the source text. Both fields are 1-based.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position { line: 1, column: 1 }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The range of source text a token was read from. `end` points just past the last character.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A lexed token. The lexer borrows `literal` from the source; the AST holds `Token<'static>`
+/// copies made with `into_owned`. Identifiers and keywords also carry their interned `symbol`.
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
     pub kind: TokenKind,
-    pub literal: String,
+    pub literal: Cow<'a, str>,
+    pub symbol: Option<Symbol>,
+    pub span: Span,
+}
+
+// Tokens compare by kind and literal only, so hand-written expectations don't need positions.
+impl PartialEq for Token<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.literal == other.literal
+    }
 }
 
 impl Display for TokenKind {
@@ -43,52 +171,124 @@ impl Display for TokenKind {
             TokenKind::ILLEGAL => "ILLEGAL",
             TokenKind::EOF => "EOF",
             TokenKind::INTEGER => "INTEGER",
+            TokenKind::STRING => "STRING",
             TokenKind::IDENTIFIER => "IDENTIFIER",
             TokenKind::ASSIGN => "=",
+            TokenKind::PLUS_ASSIGN => "+=",
+            TokenKind::MINUS_ASSIGN => "-=",
+            TokenKind::ASTERISK_ASSIGN => "*=",
+            TokenKind::SLASH_ASSIGN => "/=",
             TokenKind::PLUS => "+",
             TokenKind::MINUS => "-",
             TokenKind::BANG => "!",
             TokenKind::ASTERISK => "*",
             TokenKind::SLASH => "/",
+            TokenKind::PERCENT => "%",
             TokenKind::LT => "<",
             TokenKind::GT => ">",
+            TokenKind::LT_EQ => "<=",
+            TokenKind::GT_EQ => ">=",
             TokenKind::EQ => "==",
             TokenKind::NOT_EQ => "!=",
+            TokenKind::AND => "&&",
+            TokenKind::OR => "||",
+            TokenKind::AMPERSAND => "&",
+            TokenKind::PIPE => "|",
+            TokenKind::CARET => "^",
+            TokenKind::TILDE => "~",
+            TokenKind::LSHIFT => "<<",
+            TokenKind::RSHIFT => ">>",
+            TokenKind::NULL_COALESCE => "??",
+            TokenKind::OPTIONAL_CHAIN => "?.",
             TokenKind::COMMA => ",",
             TokenKind::SEMICOLON => ";",
             TokenKind::LPAREN => "(",
             TokenKind::RPAREN => ")",
             TokenKind::LBRACE => "{",
             TokenKind::RBRACE => "}",
+            TokenKind::LBRACKET => "[",
+            TokenKind::RBRACKET => "]",
             TokenKind::FUNCTION => "FUNCTION",
             TokenKind::LET => "LET",
             TokenKind::TRUE => "TRUE",
             TokenKind::FALSE => "FALSE",
+            TokenKind::NULL => "NULL",
             TokenKind::IF => "IF",
             TokenKind::ELSE => "ELSE",
             TokenKind::RETURN => "RETURN",
+            TokenKind::WHILE => "WHILE",
+            TokenKind::FOR => "FOR",
+            TokenKind::IN => "IN",
+            TokenKind::BREAK => "BREAK",
+            TokenKind::CONTINUE => "CONTINUE",
         };
         write!(f, "{}", s)
     }
 }
 
-impl Token {
-    pub fn new(kind: TokenKind, literal: String) -> Token {
-        Token { kind, literal }
+impl<'a> Token<'a> {
+    pub fn new(kind: TokenKind, literal: impl Into<Cow<'a, str>>) -> Token<'a> {
+        Token {
+            kind,
+            literal: literal.into(),
+            symbol: None,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(kind: TokenKind, literal: impl Into<Cow<'a, str>>, span: Span) -> Token<'a> {
+        Token {
+            kind,
+            literal: literal.into(),
+            symbol: None,
+            span,
+        }
+    }
+
+    /// Detaches the token from the source text it was lexed from. Only the kinds of token
+    /// whose text varies need to copy it.
+    pub fn into_owned(self) -> Token<'static> {
+        let literal = match self.kind.spelling() {
+            Some(spelling) => Cow::Borrowed(spelling),
+            None => Cow::Owned(self.literal.into_owned()),
+        };
+        Token {
+            kind: self.kind,
+            literal,
+            symbol: self.symbol,
+            span: self.span,
+        }
     }
 
     pub fn precedence(&self) -> Precedence {
         match self.kind {
+            TokenKind::ASSIGN
+            | TokenKind::PLUS_ASSIGN
+            | TokenKind::MINUS_ASSIGN
+            | TokenKind::ASTERISK_ASSIGN
+            | TokenKind::SLASH_ASSIGN => Precedence::Assign,
+            TokenKind::NULL_COALESCE => Precedence::NullCoalesce,
+            TokenKind::OR => Precedence::LogicalOr,
+            TokenKind::AND => Precedence::LogicalAnd,
+            TokenKind::PIPE => Precedence::BitwiseOr,
+            TokenKind::CARET => Precedence::BitwiseXor,
+            TokenKind::AMPERSAND => Precedence::BitwiseAnd,
             TokenKind::EQ | TokenKind::NOT_EQ => Precedence::Equals,
-            TokenKind::LT | TokenKind::GT => Precedence::Lessgreater,
+            TokenKind::LT | TokenKind::GT | TokenKind::LT_EQ | TokenKind::GT_EQ => {
+                Precedence::Lessgreater
+            }
+            TokenKind::LSHIFT | TokenKind::RSHIFT => Precedence::Shift,
             TokenKind::PLUS | TokenKind::MINUS => Precedence::Sum,
-            TokenKind::SLASH | TokenKind::ASTERISK => Precedence::Product,
+            TokenKind::SLASH | TokenKind::ASTERISK | TokenKind::PERCENT => Precedence::Product,
+            TokenKind::LPAREN => Precedence::Call,
+            TokenKind::LBRACKET => Precedence::Index,
+            TokenKind::OPTIONAL_CHAIN => Precedence::OptionalChain,
             _ => Precedence::Lowest,
         }
     }
 }
 
-impl Display for Token {
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
@@ -101,10 +301,20 @@ impl Display for Token {
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum Precedence {
     Lowest = 1,
-    Equals = 2,      // ==
-    Lessgreater = 3, // > or <
-    Sum = 4,         // +
-    Product = 5,     // *
-    Prefix = 6,      // -X or !X
-    Call = 7,        // my_function(x){}
+    Assign = 2,         // = or +=
+    NullCoalesce = 3,   // ??
+    LogicalOr = 4,      // ||
+    LogicalAnd = 5,     // &&
+    BitwiseOr = 6,      // |
+    BitwiseXor = 7,     // ^
+    BitwiseAnd = 8,     // &
+    Equals = 9,         // ==
+    Lessgreater = 10,   // > or <
+    Shift = 11,         // << or >>
+    Sum = 12,           // +
+    Product = 13,       // *
+    Prefix = 14,        // -X or !X or ~X
+    Call = 15,          // my_function(x){}
+    Index = 16,         // array[index]
+    OptionalChain = 17, // array?.[index] or my_function?.(x)
 }